@@ -0,0 +1,17 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use fire_alarm_service::bench;
+
+fn matching(c: &mut Criterion) {
+    let data = bench::synthetic(100_000, 1_000, 50);
+    c.bench_function("match 50 incidents against 1000 stations", |b| {
+        b.iter(|| data.match_incidents())
+    });
+
+    let incidents = data.match_incidents();
+    c.bench_function("filter 50 incidents for 100k users", |b| {
+        b.iter(|| data.filter_users(&incidents))
+    });
+}
+
+criterion_group!(benches, matching);
+criterion_main!(benches);
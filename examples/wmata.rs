@@ -1,9 +1,10 @@
-use anyhow::{Context, Error, Result};
-use fire_alarm_service::Incident;
 use fire_alarm_service::Parser;
+use fire_alarm_service::source::{
+    cache::HttpCache,
+    wmata::{BUS_INCIDENTS_ENDPOINT, ELEVATOR_INCIDENTS_ENDPOINT, INCIDENTS_ENDPOINT, Wmata},
+};
 use reqwest::Url;
 use reqwest::header::HeaderValue;
-use serde::Deserialize;
 
 #[derive(Parser)]
 #[command(version)]
@@ -13,9 +14,21 @@ struct Cli {
     key: HeaderValue,
 
     /// Endpoint for getting the incidents
-    #[arg(short, long, default_value_t = Url::parse("https://api.wmata.com/Incidents.svc/json/Incidents").unwrap())]
+    #[arg(short, long, default_value_t = Url::parse(INCIDENTS_ENDPOINT).unwrap())]
     endpoint: Url,
 
+    /// Directory to cache the incidents in, so WMATA's rate limit is respected and unchanged incidents are not downloaded again
+    #[arg(short, long)]
+    cache: Option<std::path::PathBuf>,
+
+    /// Also send elevator and escalator outages to the users who asked for them
+    #[arg(short, long)]
+    outages: bool,
+
+    /// Also send bus incidents to the users subscribed to the affected routes
+    #[arg(short, long)]
+    buses: bool,
+
     #[command(flatten)]
     args: fire_alarm_service::Args,
 
@@ -29,105 +42,49 @@ struct Cli {
 async fn main() {
     let args = Cli::parse();
 
-    let incidents = fetch_incidents(args.endpoint, args.key);
-
     #[cfg(feature = "log")]
     env_logger::Builder::new()
         .filter_level(args.verbosity.log_level_filter())
         .init();
 
-    let incidents: Vec<_> = incidents
-        .await
-        .expect("Failed to fetch incidents from WMATA")
-        .try_into()
-        .expect("Failed to Convert to FireAlarm Incidents");
-
-    #[cfg(feature = "log")]
-    log::debug!("{incidents:?}");
+    let mut source = Wmata::new(args.key).with_endpoint(args.endpoint);
+    if let Some(directory) = args.cache {
+        source = source.with_cache(HttpCache::new(directory));
+    }
+    if args.outages {
+        source = source.with_outages(Url::parse(ELEVATOR_INCIDENTS_ENDPOINT).unwrap());
+    }
+    if args.buses {
+        source = source.with_buses(Url::parse(BUS_INCIDENTS_ENDPOINT).unwrap());
+    }
 
     let args = args.args;
     fire_alarm_service::run(
-        args.timestamp,
-        sea_orm::Database::connect(args.database),
-        incidents,
+        args.state_store(),
+        sea_orm::Database::connect(args.database.clone()),
+        args.scope(source),
         &args.index,
-        args.username,
-        args.address,
-        args.password,
-        &args.relay,
+        args.smtp(),
     )
     .await
     .expect("Failed to run FireAlarm");
 }
 
-#[allow(non_snake_case)] // Necessary since the JSON key is in Pascal case
-#[derive(Deserialize)]
-struct IncidentsWmata {
-    Incidents: Vec<IncidentWmata>, // Array containing rail disruption information
-}
-
-#[allow(non_snake_case)] // Necessary since the JSON key is in Pascal case
-#[derive(Deserialize)]
-struct IncidentWmata {
-    DateUpdated: String, // Date and time (Eastern Standard Time) of last update. Will be in YYYY-MM-DDTHH:mm:SS format (e.g.: 2010-07-29T14:21:28).
-    // DelaySeverity: String, // Deprecated
-    Description: String, // Free-text description of the incident.
-                         // EmergencyText: String, // Deprecated
-                         // EndLocationFullName: String, // Deprecated
-                         // IncidentID: Option<String>,   // Unique identifier for an incident.
-                         // IncidentType: Option<String>, // Free-text description of the incident type. Usually Delay or Alert but is subject to change at any time.
-                         // LinesAffected: Option<String>, // Semi-colon and space separated list of line codes (e.g.: RD; or BL; OR; or BL; OR; RD;).
-                         // PassengerDelay: String, // Deprecated
-                         // StartLocationFullName: String, // Deprecated
-}
-
-async fn fetch_incidents(endpoint: Url, key: HeaderValue) -> Result<IncidentsWmata> {
-    Ok(reqwest::Client::builder()
-        .build()
-        .map_err(Error::from)?
-        .get(endpoint)
-        .header("api_key", key)
-        .send()
-        .await
-        .map_err(Error::from)?
-        .json::<IncidentsWmata>()
-        .await?)
-}
-
-impl TryFrom<IncidentsWmata> for Vec<Incident> {
-    type Error = Error;
-    fn try_from(value: IncidentsWmata) -> Result<Self> {
-        value
-            .Incidents
-            .into_iter()
-            .map(|incident| incident.try_into())
-            .collect()
-    }
-}
-
-impl TryFrom<IncidentWmata> for Incident {
-    type Error = Error;
-    fn try_from(value: IncidentWmata) -> Result<Self> {
-        use chrono::TimeZone;
-        let eastern_datetime = chrono::NaiveDateTime::parse_from_str(&value.DateUpdated, "%FT%T")?;
-        let eastern_datetime_tz = chrono_tz::US::Eastern
-            .from_local_datetime(&eastern_datetime)
-            .single()
-            .context(
-                "Parsed datetime falls in a fold or gap in US Eastern timezone or there was an error.",
-            )?;
-        Ok(Incident::new(
-            eastern_datetime_tz.to_utc(),
-            value.Description,
-        ))
-    }
-}
-
 #[cfg(test)]
 mod test {
     use std::env;
 
-    use super::fetch_incidents;
+    use fire_alarm_service::IncidentSource;
+    use fire_alarm_service::source::wmata::{INCIDENTS_ENDPOINT, Wmata};
+
+    fn source() -> Wmata {
+        let endpoint = env::var("WMATA_ENDPOINT")
+            .unwrap_or_else(|_| String::from(INCIDENTS_ENDPOINT))
+            .parse()
+            .unwrap();
+        let key = env::var("WMATA_API_KEY").unwrap().try_into().unwrap();
+        Wmata::new(key).with_endpoint(endpoint)
+    }
 
     #[tokio::test]
     async fn validate_api_key() {
@@ -148,24 +105,13 @@ mod test {
 
     #[tokio::test]
     async fn test_fetch_incidents() {
-        let endpoint = env::var("WMATA_ENDPOINT")
-            .unwrap_or_else(|_| String::from("https://api.wmata.com/Incidents.svc/json/Incidents"))
-            .parse()
-            .unwrap();
-        let key = env::var("WMATA_API_KEY").unwrap().try_into().unwrap();
-        fetch_incidents(endpoint, key).await.unwrap();
+        source().fetch().await.unwrap();
     }
 
     #[tokio::test]
     async fn test_main() {
-        let endpoint = env::var("WMATA_ENDPOINT")
-            .unwrap_or_else(|_| String::from("https://api.wmata.com/Incidents.svc/json/Incidents"))
-            .parse()
-            .unwrap();
-        let key = env::var("WMATA_API_KEY").unwrap().try_into().unwrap();
-        let incidents = fetch_incidents(endpoint, key);
-
         let timestamp = env::var("TIMESTAMP").unwrap_or_else(|_| String::from("timestamp.txt"));
+        let state = fire_alarm_service::state::FileStore::new(timestamp);
         let database = match env::var("DATABASE") {
             Ok(opt) => sea_orm::Database::connect(opt).await,
             Err(_) => {
@@ -176,11 +122,10 @@ mod test {
         };
         let address = lettre::Address::new("obiwan.konobi", "jedi.com").unwrap();
 
-        let incidents: Vec<crate::Incident> = incidents.await.unwrap().try_into().unwrap();
         fire_alarm_service::test_run(
-            timestamp,
+            state,
             std::future::ready(database),
-            incidents,
+            source(),
             "index.html",
             address,
         )
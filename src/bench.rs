@@ -0,0 +1,116 @@
+//! Synthetic data for benchmarking how incidents are matched to users, only built with the `bench` feature
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use lettre::Address;
+
+use crate::database::{rail_line, station};
+use crate::{Aliased, Incident, Line, Subscriber, expand_segments, filter_subscribed, matcher};
+
+/// Made up stations, lines, users, and incidents
+pub struct Synthetic {
+    stations: Vec<Aliased>,
+    lines: Vec<Line>,
+    users: Vec<Subscriber>,
+    incidents: Vec<Incident>,
+}
+
+/// Makes up the given number of users who are each subscribed to a few stations, with every tenth following a whole line,
+/// along with incidents that each mention a couple of stations or a stretch of line
+pub fn synthetic(users: usize, stations: usize, incidents: usize) -> Synthetic {
+    const STATIONS_PER_LINE: usize = 100;
+
+    let stations: Vec<Aliased> = (0..stations)
+        .map(|index| {
+            let station = station::Model {
+                id: index as i32,
+                name: format!("Station {index}"),
+                code: Some(format!("S{index}")),
+                agency_id: None,
+            };
+            (station, Vec::new())
+        })
+        .collect();
+    let lines: Vec<Line> = stations
+        .chunks(STATIONS_PER_LINE)
+        .enumerate()
+        .map(|(index, stations)| {
+            let line = rail_line::Model {
+                id: index as i32,
+                name: format!("Line {index}"),
+                code: Some(format!("L{index}")),
+                agency_id: None,
+            };
+            (line, stations.to_vec())
+        })
+        .collect();
+
+    // Spreads the subscriptions around deterministically so every run measures the same thing
+    let pick = |seed: usize| &stations[seed.wrapping_mul(7919) % stations.len()];
+    let users = (0..users)
+        .map(|index| Subscriber {
+            id: index as i32,
+            email: Address::new(format!("user{index}"), "example.com").unwrap(),
+            stations: (0..3)
+                .map(|offset| pick(index * 3 + offset).clone())
+                .collect(),
+            sent: HashMap::new(),
+            since: DateTime::<Utc>::MIN_UTC,
+            outages: Vec::new(),
+            routes: Vec::new(),
+            lines: if index % 10 == 0 {
+                vec![lines[index % lines.len()].clone()]
+            } else {
+                Vec::new()
+            },
+            rules: Vec::new(),
+        })
+        .collect();
+    let incidents = (0..incidents)
+        .map(|index| {
+            let description = match index % 3 {
+                0 => format!(
+                    "Delays at {} and {}",
+                    pick(index).0.name,
+                    pick(index + 1).0.name
+                ),
+                1 => format!(
+                    "Single tracking between {} and {} due to a track problem",
+                    stations[index % stations.len()].0.name,
+                    stations[(index + 5) % stations.len()].0.name
+                ),
+                _ => format!("{} trains are delayed", lines[index % lines.len()].0.name),
+            };
+            Incident::new(Utc::now(), description).with_stops([format!("S{index}")])
+        })
+        .collect();
+
+    Synthetic {
+        stations,
+        lines,
+        users,
+        incidents,
+    }
+}
+
+impl Synthetic {
+    /// Works out what each incident affects, which is done once per run
+    pub fn match_incidents(&self) -> Vec<Incident> {
+        let matcher = matcher::Matcher::new(&self.stations, &[], &self.lines).unwrap();
+        let mut incidents = self.incidents.clone();
+        for incident in &mut incidents {
+            matcher.apply(incident);
+        }
+        expand_segments(&mut incidents, &self.lines);
+        incidents
+    }
+
+    /// Filters the matched incidents for every user, returning how many were kept altogether
+    pub fn filter_users(&self, incidents: &[Incident]) -> usize {
+        self.users
+            .iter()
+            .map(|user| filter_subscribed::<Vec<_>>(incidents, user).len())
+            .sum()
+    }
+}
@@ -0,0 +1,39 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "Agency")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false, unique)]
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(has_many = "super::bus_route::Entity")]
+    BusRoute,
+    #[sea_orm(has_many = "super::rail_line::Entity")]
+    RailLine,
+    #[sea_orm(has_many = "super::station::Entity")]
+    Station,
+}
+
+impl Related<super::bus_route::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::BusRoute.def()
+    }
+}
+
+impl Related<super::rail_line::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::RailLine.def()
+    }
+}
+
+impl Related<super::station::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Station.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,49 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "BusRoute")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false, unique)]
+    pub id: i32,
+    /// Unique within the agency, see [`crate::create_indexes`]
+    pub code: String,
+    pub name: String,
+    pub agency_id: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::agency::Entity",
+        from = "Column::AgencyId",
+        to = "super::agency::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Agency,
+    #[sea_orm(has_many = "super::user_bus_route::Entity")]
+    UserBusRoute,
+}
+
+impl Related<super::agency::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Agency.def()
+    }
+}
+
+impl Related<super::user_bus_route::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserBusRoute.def()
+    }
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::user_bus_route::Relation::User.def()
+    }
+    fn via() -> Option<RelationDef> {
+        Some(super::user_bus_route::Relation::BusRoute.def().rev())
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,5 +1,3 @@
-//! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.19
-
 use sea_orm::entity::prelude::*;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
@@ -9,6 +7,8 @@ pub struct Model {
     pub line_id: i32,
     #[sea_orm(primary_key, auto_increment = false)]
     pub station_id: i32,
+    #[sea_orm(default_value = 0)]
+    pub sequence: i32,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
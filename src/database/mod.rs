@@ -1,9 +1,20 @@
-//! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.19
+//! `SeaORM` entities for the tables made by [`crate::setup_db`]
 
 pub mod prelude;
 
+pub mod active_incident;
+pub mod agency;
+pub mod bus_route;
 pub mod line_station;
 pub mod rail_line;
+pub mod run_state;
+pub mod sea_orm_active_enums;
+pub mod sent_incident;
 pub mod station;
+pub mod station_alias;
+pub mod station_code;
 pub mod user;
+pub mod user_bus_route;
+pub mod user_line;
+pub mod user_rule;
 pub mod user_station;
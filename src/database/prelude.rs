@@ -1,9 +1,17 @@
-//! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.19
-
 #![allow(unused_imports)]
 
+pub use super::active_incident::Entity as ActiveIncident;
+pub use super::agency::Entity as Agency;
+pub use super::bus_route::Entity as BusRoute;
 pub use super::line_station::Entity as LineStation;
 pub use super::rail_line::Entity as RailLine;
+pub use super::run_state::Entity as RunState;
+pub use super::sent_incident::Entity as SentIncident;
 pub use super::station::Entity as Station;
+pub use super::station_alias::Entity as StationAlias;
+pub use super::station_code::Entity as StationCode;
 pub use super::user::Entity as User;
+pub use super::user_bus_route::Entity as UserBusRoute;
+pub use super::user_line::Entity as UserLine;
+pub use super::user_rule::Entity as UserRule;
 pub use super::user_station::Entity as UserStation;
@@ -1,5 +1,3 @@
-//! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.19
-
 use sea_orm::entity::prelude::*;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
@@ -7,14 +5,32 @@ use sea_orm::entity::prelude::*;
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false, unique)]
     pub id: i32,
-    #[sea_orm(unique)]
+    /// Unique within the agency, see [`crate::create_indexes`]
     pub name: String,
+    pub code: Option<String>,
+    pub agency_id: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::agency::Entity",
+        from = "Column::AgencyId",
+        to = "super::agency::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Agency,
     #[sea_orm(has_many = "super::line_station::Entity")]
     LineStation,
+    #[sea_orm(has_many = "super::user_line::Entity")]
+    UserLine,
+}
+
+impl Related<super::agency::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Agency.def()
+    }
 }
 
 impl Related<super::line_station::Entity> for Entity {
@@ -23,6 +39,12 @@ impl Related<super::line_station::Entity> for Entity {
     }
 }
 
+impl Related<super::user_line::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserLine.def()
+    }
+}
+
 impl Related<super::station::Entity> for Entity {
     fn to() -> RelationDef {
         super::line_station::Relation::Station.def()
@@ -32,4 +54,13 @@ impl Related<super::station::Entity> for Entity {
     }
 }
 
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::user_line::Relation::User.def()
+    }
+    fn via() -> Option<RelationDef> {
+        Some(super::user_line::Relation::RailLine.def().rev())
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,10 @@
+use sea_orm::entity::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, Eq, EnumIter, DeriveActiveEnum)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::N(16))")]
+pub enum RuleKind {
+    #[sea_orm(string_value = "keyword")]
+    Keyword,
+    #[sea_orm(string_value = "regex")]
+    Regex,
+}
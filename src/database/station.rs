@@ -1,5 +1,3 @@
-//! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.19
-
 use sea_orm::entity::prelude::*;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
@@ -7,24 +5,64 @@ use sea_orm::entity::prelude::*;
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false, unique)]
     pub id: i32,
-    #[sea_orm(unique)]
+    /// Unique within the agency, see [`crate::create_indexes`]
     pub name: String,
+    pub code: Option<String>,
+    pub agency_id: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::agency::Entity",
+        from = "Column::AgencyId",
+        to = "super::agency::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    Agency,
+    #[sea_orm(has_many = "super::active_incident::Entity")]
+    ActiveIncident,
     #[sea_orm(has_many = "super::line_station::Entity")]
     LineStation,
+    #[sea_orm(has_many = "super::station_alias::Entity")]
+    StationAlias,
+    #[sea_orm(has_many = "super::station_code::Entity")]
+    StationCode,
     #[sea_orm(has_many = "super::user_station::Entity")]
     UserStation,
 }
 
+impl Related<super::agency::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Agency.def()
+    }
+}
+
+impl Related<super::active_incident::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ActiveIncident.def()
+    }
+}
+
 impl Related<super::line_station::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::LineStation.def()
     }
 }
 
+impl Related<super::station_alias::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::StationAlias.def()
+    }
+}
+
+impl Related<super::station_code::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::StationCode.def()
+    }
+}
+
 impl Related<super::user_station::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::UserStation.def()
@@ -1,5 +1,3 @@
-//! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.19
-
 use sea_orm::entity::prelude::*;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
@@ -9,20 +7,71 @@ pub struct Model {
     pub id: i32,
     #[sea_orm(unique)]
     pub email: String,
+    pub since: Option<DateTimeUtc>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::sent_incident::Entity")]
+    SentIncident,
+    #[sea_orm(has_many = "super::user_bus_route::Entity")]
+    UserBusRoute,
+    #[sea_orm(has_many = "super::user_line::Entity")]
+    UserLine,
+    #[sea_orm(has_many = "super::user_rule::Entity")]
+    UserRule,
     #[sea_orm(has_many = "super::user_station::Entity")]
     UserStation,
 }
 
+impl Related<super::sent_incident::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::SentIncident.def()
+    }
+}
+
+impl Related<super::user_bus_route::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserBusRoute.def()
+    }
+}
+
+impl Related<super::user_line::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserLine.def()
+    }
+}
+
+impl Related<super::user_rule::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserRule.def()
+    }
+}
+
 impl Related<super::user_station::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::UserStation.def()
     }
 }
 
+impl Related<super::bus_route::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::user_bus_route::Relation::BusRoute.def()
+    }
+    fn via() -> Option<RelationDef> {
+        Some(super::user_bus_route::Relation::User.def().rev())
+    }
+}
+
+impl Related<super::rail_line::Entity> for Entity {
+    fn to() -> RelationDef {
+        super::user_line::Relation::RailLine.def()
+    }
+    fn via() -> Option<RelationDef> {
+        Some(super::user_line::Relation::User.def().rev())
+    }
+}
+
 impl Related<super::station::Entity> for Entity {
     fn to() -> RelationDef {
         super::user_station::Relation::Station.def()
@@ -0,0 +1,60 @@
+use super::sea_orm_active_enums::RuleKind;
+use sea_orm::entity::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "UserRule")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub user_id: i32,
+    pub kind: RuleKind,
+    pub pattern: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::user::Entity",
+        from = "Column::UserId",
+        to = "super::user::Column::Id",
+        on_update = "NoAction",
+        on_delete = "NoAction"
+    )]
+    User,
+}
+
+impl Related<super::user::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::User.def()
+    }
+}
+
+#[async_trait::async_trait]
+impl ActiveModelBehavior for ActiveModel {
+    /// Rejects rules that could never match anything, so the mistake is caught when the rule is saved rather than on every run
+    async fn before_save<C>(self, db: &C, insert: bool) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+    {
+        // An update that only changes one of the two still has to make sense with the other one as it is stored
+        let stored = match self.id.try_as_ref() {
+            Some(id) if !insert && (self.kind.is_not_set() || self.pattern.is_not_set()) => {
+                Entity::find_by_id(*id).one(db).await?
+            }
+            _ => None,
+        };
+        let kind = self
+            .kind
+            .try_as_ref()
+            .or(stored.as_ref().map(|rule| &rule.kind));
+        let pattern = self
+            .pattern
+            .try_as_ref()
+            .or(stored.as_ref().map(|rule| &rule.pattern));
+        if let (Some(kind), Some(pattern)) = (kind, pattern) {
+            crate::rule::validate(kind, pattern)
+                .map_err(|error| DbErr::Custom(error.to_string()))?;
+        }
+        Ok(self)
+    }
+}
@@ -1,5 +1,3 @@
-//! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.19
-
 use sea_orm::entity::prelude::*;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
@@ -9,6 +7,10 @@ pub struct Model {
     pub user_id: i32,
     #[sea_orm(primary_key, auto_increment = false)]
     pub station_id: i32,
+    #[sea_orm(default_value = false)]
+    pub elevator_outages: bool,
+    #[sea_orm(default_value = false)]
+    pub escalator_outages: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
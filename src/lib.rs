@@ -1,7 +1,12 @@
-use std::{fmt::Debug, path::Path, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    path::Path,
+    sync::Arc,
+};
 
 use camino::Utf8PathBuf;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeDelta, Utc};
 use lettre::{Address, AsyncSmtpTransport, AsyncTransport, Tokio1Executor, message, transport};
 use sea_orm::{ConnectionTrait, DbErr};
 use serde::{Deserialize, Serialize};
@@ -10,7 +15,27 @@ use tera::Tera;
 pub use clap::Parser;
 
 mod database; // Imports the datatypes and logic generated by [`sea-orm-cli`] to interact with the SQL database
-use crate::database::prelude::*;
+use crate::database::{
+    active_incident, bus_route, line_station, prelude::*, rail_line, sent_incident, station,
+    station_alias, station_code, user,
+};
+
+mod matcher; // Works out which stations and lines each incident affects once for everyone
+mod rule; // Checks and runs users' own keyword and regex filters
+mod text; // Normalizes text so station names can be found in incident messages
+
+pub mod source;
+pub use source::IncidentSource;
+
+pub mod state;
+pub use state::StateStore;
+
+#[cfg(feature = "webhook")]
+pub mod webhook;
+
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench;
 
 /// Send only the transit notifications that users care about
 #[derive(Parser)]
@@ -36,11 +61,21 @@ pub struct Args {
     #[cfg_attr(feature = "env", arg(env))]
     pub relay: String,
 
-    /// Filename for the previous timestamp
+    /// Where to keep the time of the last successful run
+    #[arg(long, value_enum, default_value_t = StateBackend::File)]
+    #[cfg_attr(feature = "env", arg(env))]
+    pub state: StateBackend,
+
+    /// Filename for the previous timestamp when the state is kept in a file
     #[arg(short, long, default_value_t = Utf8PathBuf::from("timestamp.txt"))]
     #[cfg_attr(feature = "env", arg(env))]
     pub timestamp: Utf8PathBuf,
 
+    /// Name for the previous timestamp when the state is kept in the database
+    #[arg(long, default_value = env!("CARGO_PKG_NAME"))]
+    #[cfg_attr(feature = "env", arg(env))]
+    pub state_key: String,
+
     /// Filename for database of users
     #[arg(short, long)]
     #[cfg_attr(feature = "env", arg(env))]
@@ -49,45 +84,106 @@ pub struct Args {
     #[arg(short, long, default_value_t = Utf8PathBuf::from("index.html"))]
     #[cfg_attr(feature = "env", arg(env))]
     pub index: Utf8PathBuf,
+
+    /// ID of the agency the incidents come from, so they are only matched against its stations and routes
+    #[arg(long)]
+    #[cfg_attr(feature = "env", arg(env))]
+    pub agency: Option<String>,
+}
+
+impl Args {
+    /// Creates the [`StateStore`] that was chosen on the command line
+    pub fn state_store(&self) -> state::Store {
+        match self.state {
+            StateBackend::File => state::Store::File(state::FileStore::new(&self.timestamp)),
+            StateBackend::Database => {
+                state::Store::Database(state::DatabaseStore::new(&self.state_key))
+            }
+        }
+    }
+
+    /// Collects the settings for the SMTP relay server that were given on the command line
+    pub fn smtp(&self) -> Smtp {
+        Smtp {
+            address: self.address.clone(),
+            username: self.username.clone(),
+            password: self.password.clone(),
+            relay: self.relay.clone(),
+        }
+    }
+
+    /// Marks the incidents from the source as belonging to the agency that was chosen on the command line, if any
+    pub fn scope<S: IncidentSource>(&self, source: S) -> source::Scoped<S> {
+        source::Scoped {
+            agency: self.agency.clone(),
+            source,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum StateBackend {
+    /// Plain text file containing the timestamp
+    File,
+
+    /// Table in the database of users, for running several instances at once
+    Database,
+}
+
+/// Where the emails are sent from and the SMTP relay server they are sent through
+#[derive(Clone, Debug)]
+pub struct Smtp {
+    /// Email address to send from
+    pub address: Address,
+
+    /// Username for the SMTP relay server, will default to address
+    pub username: Option<String>,
+
+    /// Password for the SMTP relay server
+    pub password: String,
+
+    /// URL of the SMTP relay server
+    pub relay: String,
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
 /// Main entrypoint for this library which executes all the logic
 pub async fn run<C: ConnectionTrait>(
-    timestamp: impl AsRef<Path>,
+    state: impl StateStore,
     database: impl DatabaseFuture<C>,
-    incidents: impl IntoIterator<Item = Incident>,
+    source: impl IncidentSource,
     index: impl AsRef<Path>,
-    username: Option<String>,
-    address: Address,
-    password: String,
-    relay: &str,
+    smtp: Smtp,
 ) -> Result<()> {
+    let Smtp {
+        address,
+        username,
+        password,
+        relay,
+    } = smtp;
     let transport = create_transport(
         username.clone().unwrap_or_else(|| address.to_string()),
         password,
-        relay,
+        &relay,
     )?;
-    execute(
-        timestamp, database, incidents, index, username, address, transport,
-    )
-    .await
-    .map(|_| ())
+    execute(state, database, source, index, username, address, transport)
+        .await
+        .map(|_| ())
 }
 
 /// Test entrypoint to check the messages without actually sending them
 pub async fn test_run<C: ConnectionTrait>(
-    timestamp: impl AsRef<Path>,
+    state: impl StateStore,
     database: impl DatabaseFuture<C>,
-    incidents: impl IntoIterator<Item = Incident>,
+    source: impl IncidentSource,
     index: impl AsRef<Path>,
     address: Address,
 ) -> Result<()> {
     let transport = execute(
-        timestamp,
+        state,
         database,
-        incidents,
+        source,
         index,
         None,
         address,
@@ -105,9 +201,9 @@ pub async fn test_run<C: ConnectionTrait>(
 
 #[cfg(feature = "file-transport")]
 pub async fn file_run<C: ConnectionTrait>(
-    timestamp: impl AsRef<Path>,
+    state: impl StateStore,
     database: impl DatabaseFuture<C>,
-    incidents: impl IntoIterator<Item = Incident>,
+    source: impl IncidentSource,
     index: impl AsRef<Path>,
     address: Address,
 ) -> Result<()> {
@@ -118,9 +214,9 @@ pub async fn file_run<C: ConnectionTrait>(
     println!("{path:?}");
 
     execute(
-        timestamp,
+        state,
         database,
-        incidents,
+        source,
         index,
         None,
         address,
@@ -138,43 +234,147 @@ pub async fn test_connection(username: String, password: String, relay: &str) ->
     Ok(result)
 }
 
-/// Sets up the table definitions for [User], [Station], and [UserStation], useful in testing with in-memory SQLite databases
+/// Creates the indexes that keep station and line names, and bus route codes, unique within each agency
+///
+/// Databases made before stations, lines, and routes belonged to an agency have a unique index on the name alone,
+/// which has to be dropped before these are created or the same name could not be used by two agencies
+pub async fn create_indexes(db: &impl sea_orm::ConnectionTrait) -> std::result::Result<(), DbErr> {
+    use sea_orm::{
+        DbBackend,
+        sea_query::{ConditionalStatement, Expr, Index},
+    };
+
+    let backend = db.get_database_backend();
+
+    // Names and codes only have to be unique within an agency, e.g. several systems have a "Union Station"
+    let index_create_statements = [
+        Index::create()
+            .name("idx-station-agency-name")
+            .table(Station)
+            .col(station::Column::AgencyId)
+            .col(station::Column::Name)
+            .unique()
+            .to_owned(),
+        Index::create()
+            .name("idx-rail-line-agency-name")
+            .table(RailLine)
+            .col(rail_line::Column::AgencyId)
+            .col(rail_line::Column::Name)
+            .unique()
+            .to_owned(),
+        Index::create()
+            .name("idx-bus-route-agency-code")
+            .table(BusRoute)
+            .col(bus_route::Column::AgencyId)
+            .col(bus_route::Column::Code)
+            .unique()
+            .to_owned(),
+    ];
+    for statement in index_create_statements {
+        db.execute(backend.build(&statement)).await?;
+    }
+
+    // NULLs never conflict in a unique index, so the ones without an agency need their own index to stay unique
+    if backend == DbBackend::MySql {
+        // MySQL has no partial indexes, but treating a missing agency as an empty one has the same effect
+        for (name, table, column) in [
+            ("idx-station-unowned-name", "Station", "name"),
+            ("idx-rail-line-unowned-name", "RailLine", "name"),
+            ("idx-bus-route-unowned-code", "BusRoute", "code"),
+        ] {
+            db.execute_unprepared(&format!(
+                "CREATE UNIQUE INDEX `{name}` ON `{table}` ((IFNULL(`agency_id`, '')), `{column}`)"
+            ))
+            .await?;
+        }
+    } else {
+        let unowned_index_statements = [
+            Index::create()
+                .name("idx-station-unowned-name")
+                .table(Station)
+                .col(station::Column::Name)
+                .unique()
+                .and_where(Expr::col(station::Column::AgencyId).is_null())
+                .to_owned(),
+            Index::create()
+                .name("idx-rail-line-unowned-name")
+                .table(RailLine)
+                .col(rail_line::Column::Name)
+                .unique()
+                .and_where(Expr::col(rail_line::Column::AgencyId).is_null())
+                .to_owned(),
+            Index::create()
+                .name("idx-bus-route-unowned-code")
+                .table(BusRoute)
+                .col(bus_route::Column::Code)
+                .unique()
+                .and_where(Expr::col(bus_route::Column::AgencyId).is_null())
+                .to_owned(),
+        ];
+        for statement in unowned_index_statements {
+            db.execute(backend.build(&statement)).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Sets up the table definitions for [Agency], [User], [Station], [StationAlias], [StationCode], [UserStation], [RailLine], [LineStation], [UserLine], [BusRoute], [UserBusRoute],
+/// [UserRule], [SentIncident], [ActiveIncident], and [RunState] along with [`create_indexes`],
+/// useful in testing with in-memory SQLite databases
 pub async fn setup_db(
     db: &impl sea_orm::ConnectionTrait,
     add_dummy_data: bool,
 ) -> std::result::Result<(), DbErr> {
-    use crate::database::{station, user, user_station};
+    use crate::database::{user, user_station};
     use sea_orm::{ActiveValue, EntityTrait};
 
     let backend = db.get_database_backend();
     let schema = sea_orm::Schema::new(backend);
 
     let table_create_statements = [
+        schema.create_table_from_entity(Agency),
         schema.create_table_from_entity(User),
         schema.create_table_from_entity(Station),
+        schema.create_table_from_entity(StationAlias),
+        schema.create_table_from_entity(StationCode),
         schema.create_table_from_entity(UserStation),
+        schema.create_table_from_entity(RailLine),
+        schema.create_table_from_entity(LineStation),
+        schema.create_table_from_entity(UserLine),
+        schema.create_table_from_entity(BusRoute),
+        schema.create_table_from_entity(UserBusRoute),
+        schema.create_table_from_entity(UserRule),
+        schema.create_table_from_entity(SentIncident),
+        schema.create_table_from_entity(ActiveIncident),
+        schema.create_table_from_entity(RunState),
     ];
     for statement in table_create_statements {
         db.execute(backend.build(&statement)).await?;
     }
 
+    create_indexes(db).await?;
+
     if add_dummy_data {
         let stations = [
             station::ActiveModel {
                 id: ActiveValue::Set(1),
                 name: ActiveValue::Set(String::from("Hello")),
+                ..Default::default()
             },
             station::ActiveModel {
                 id: ActiveValue::Set(2),
                 name: ActiveValue::Set(String::from("General")),
+                ..Default::default()
             },
             station::ActiveModel {
                 id: ActiveValue::Set(3),
                 name: ActiveValue::Set(String::from("high ground")),
+                ..Default::default()
             },
             station::ActiveModel {
                 id: ActiveValue::Set(4),
                 name: ActiveValue::Set(String::from("power")),
+                ..Default::default()
             },
         ];
         Station::insert_many(stations)
@@ -185,10 +385,12 @@ pub async fn setup_db(
             user::ActiveModel {
                 id: ActiveValue::Set(1),
                 email: ActiveValue::Set(String::from("sand.hater@jedi.com")),
+                ..Default::default()
             },
             user::ActiveModel {
                 id: ActiveValue::Set(2),
                 email: ActiveValue::Set(String::from("lightsaber.collector@cis.com")),
+                ..Default::default()
             },
         ];
         User::insert_many(users).exec_without_returning(db).await?;
@@ -197,10 +399,12 @@ pub async fn setup_db(
             user_station::ActiveModel {
                 user_id: ActiveValue::Set(1),    // Anakin
                 station_id: ActiveValue::Set(3), // High Ground
+                ..Default::default()
             },
             user_station::ActiveModel {
                 user_id: ActiveValue::Set(2),    // General Grievous
                 station_id: ActiveValue::Set(1), // Hello there
+                ..Default::default()
             },
         ];
         UserStation::insert_many(user_stations)
@@ -211,34 +415,80 @@ pub async fn setup_db(
     Ok(())
 }
 
-const TEMPLATE: &str = "Email Template";
+/// Named as HTML so that Tera escapes the incidents put into it
+const TEMPLATE: &str = "email.html";
+
+/// How long an incident can go without being reported before it is forgotten,
+/// since sources that only report some of the incidents at a time never say when one was resolved
+const FORGET_AFTER: TimeDelta = TimeDelta::days(7);
 
-/// Actually handles all the business logic for gathering the notifications, filtering them by time and subscription for each user, and sending the email
+/// Actually handles all the business logic for gathering the notifications, filtering them by subscription and what was already sent for each user, and sending the email
 async fn execute<C: ConnectionTrait, T: AsyncTransport<Error: Debug> + Send + Sync + 'static>(
-    timestamp: impl AsRef<Path>,
+    state: impl StateStore,
     database: impl DatabaseFuture<C>,
-    incidents: impl IntoIterator<Item = Incident>,
+    source: impl IncidentSource,
     index: impl AsRef<Path>,
     username: Option<String>,
     address: Address,
     transport: T,
 ) -> Result<T> {
     // Needs to return the transport so [`test_run`] can display the messages
-    let timestamp = fetch_and_update_timestamp(timestamp);
+    // Taken before fetching so anything published while this runs is picked up next time
+    let started = Utc::now();
+    let mut incidents = dedup_incidents(filter_expired::<Vec<_>>(source.fetch().await?, started));
+    let present: HashSet<_> = incidents.iter().map(Incident::key).collect();
+
+    // Asking for a future for the database connection rather than for the connection directly means the initial connection request is sent early,
+    // so it will be processing or already done by the time it is needed here.
+    // Plus this allows me to setup the tables for an in-memory SQLite database for testing before calling this function.
+    let db = database.await?;
+    // A new deployment starts from now like a new user does rather than sending everyone the whole backlog
+    let watermark = state.fetch(&db).await?.unwrap_or(started);
+    let previously_active = fetch_active(&db).await?;
+    let stations = fetch_stations(&db).await?;
+    let lines = fetch_lines(&db).await?;
+    let matcher = matcher::Matcher::new(&stations, &fetch_station_codes(&db).await?, &lines)?;
+    for incident in &mut incidents {
+        matcher.apply(incident);
+    }
+    expand_segments(&mut incidents, &lines);
+    let active = find_active(&incidents, started);
 
-    let incidents: Arc<Vec<_>> = Arc::new(filter_timestamp(incidents, timestamp.await?));
+    let incidents: Arc<[Incident]> = incidents.into();
     #[cfg(feature = "log")]
     log::debug!("{incidents:?}");
 
+    let users = fetch_users(&db, watermark, &stations, &lines).await?;
+    // Anything a user was sent which is no longer in the feed has been resolved,
+    // unless the source only reported some of the incidents or the incident came from another agency
+    let complete = source.complete();
+    let scope = source.agency();
+    let resolved: Arc<HashMap<_, _>> = Arc::new(
+        users
+            .iter()
+            .flat_map(|user| user.sent.iter())
+            .filter(|(key, sent)| {
+                complete
+                    && !present.contains(*key)
+                    && scope.is_none_or(|agency| sent.agency.as_deref() == Some(agency))
+            })
+            .map(|(key, _)| {
+                let stations = previously_active.get(key).cloned().unwrap_or_default();
+                (key.clone(), stations)
+            })
+            .collect(),
+    );
+
     let template = Arc::new(create_template(index)?);
     let message_builder = Arc::new(create_message_builder(username, address));
     let transport = Arc::new(transport);
 
     let mut join_set = tokio::task::JoinSet::new();
-    for user in fetch_users(database).await? {
+    for user in users {
         join_set.spawn(process(
             // Processes each user in parallel
             incidents.clone(),
+            resolved.clone(),
             user,
             template.clone(),
             message_builder.clone(),
@@ -247,15 +497,39 @@ async fn execute<C: ConnectionTrait, T: AsyncTransport<Error: Debug> + Send + Sy
     }
 
     let results = join_set.join_all().await;
+    let mut failed = false;
     for result in results {
+        let result = match result {
+            Ok(delivery) => record_delivery(&db, delivery).await,
+            Err(error) => Err(error),
+        };
         if let Err(error) = result {
             // If there is an error report it and continue
             #[cfg(feature = "log")]
             log::error!("{error}");
             #[cfg(not(feature = "log"))]
             eprintln!("{error}");
+            failed = true;
         }
     }
+    // Whoever failed still needs to know which stations the resolved incidents were affecting when they are retried
+    let gone = if failed || !complete {
+        Vec::new()
+    } else {
+        previously_active.into_keys().collect()
+    };
+    update_active(&db, &present, gone, active, scope).await?;
+    forget_stale(&db, &present, started).await?;
+
+    if failed {
+        // Users who have never been notified fall back on this timestamp, so leaving it where it was means they are retried on the next run too
+        #[cfg(feature = "log")]
+        log::warn!("Not advancing the timestamp since some users were not notified");
+        #[cfg(not(feature = "log"))]
+        eprintln!("Not advancing the timestamp since some users were not notified");
+    } else {
+        state.update(&db, started).await?;
+    }
 
     transport.shutdown().await;
     Arc::into_inner(transport).ok_or(Error::TransportError)
@@ -292,6 +566,39 @@ pub enum Error {
 
     #[error("Template Error: {0}")]
     TemplateError(#[from] tera::Error),
+
+    #[error("Failed to fetch incidents: {0}")]
+    HttpError(#[from] reqwest::Error),
+
+    #[error("{0} does not exist or is ambiguous in the {1} timezone")]
+    LocalTimeError(chrono::NaiveDateTime, chrono_tz::Tz),
+
+    #[error("Nothing found at {0} in the incidents")]
+    MissingFieldError(String),
+
+    #[error("Rate limited until {0} with nothing cached")]
+    RateLimitedError(DateTime<Utc>),
+
+    #[error("Failed to build the station matcher: {0}")]
+    MatcherError(#[from] aho_corasick::BuildError),
+
+    #[error("Invalid regex in rule: {0}")]
+    RegexError(#[from] regex::Error),
+
+    #[error("Keyword rule {0:?} does not have any words in it")]
+    EmptyKeywordError(String),
+
+    #[cfg(feature = "gtfs-rt")]
+    #[error("Failed to decode GTFS-Realtime feed: {0}")]
+    ProtobufError(#[from] prost::DecodeError),
+
+    #[cfg(feature = "feed")]
+    #[error("Failed to parse RSS or Atom feed: {0}")]
+    FeedError(#[from] feed_rs::parser::ParseFeedError),
+
+    #[cfg(feature = "cap")]
+    #[error("Failed to parse CAP alert: {0}")]
+    CapError(#[from] quick_xml::DeError),
 }
 
 #[cfg_attr(test, derive(PartialEq))]
@@ -299,46 +606,185 @@ pub enum Error {
 pub struct Incident<D: AsRef<str> = String> {
     timestamp: DateTime<Utc>,
     description: D,
+
+    /// Identifier assigned by the source which stays the same when the incident is edited
+    #[serde(default)]
+    id: Option<String>,
+
+    /// Free-text category given by the source, e.g. "Delay" or "Alert" for WMATA
+    #[serde(default, rename = "type")]
+    kind: Option<String>,
+
+    /// Codes of the lines affected by the incident, e.g. "RD" for the WMATA Red Line
+    #[serde(default)]
+    lines: Vec<String>,
+
+    #[serde(default)]
+    severity: Option<Severity>,
+
+    /// How soon riders need to act on the incident, e.g. from a CAP alert
+    #[serde(default)]
+    urgency: Option<Urgency>,
+
+    /// Codes of the stops or stations affected by the incident, matched against [`station::Model::code`] and any other codes in [`StationCode`]
+    #[serde(default)]
+    stops: Vec<String>,
+
+    /// IDs of the bus routes affected by the incident, matched against [`bus_route::Model::code`]
+    #[serde(default)]
+    routes: Vec<String>,
+
+    /// When the incident is no longer in effect, if the source says so
+    #[serde(default)]
+    expires: Option<DateTime<Utc>>,
+
+    /// Stations and lines the incident mentions or lists, these are filled in by the [`matcher::Matcher`] and [`expand_segments`]
+    /// rather than given by the source
+    #[serde(skip)]
+    affected: matcher::Affected,
+
+    /// The user's rules that the incident matched, filled in for each user so the email can say why it was sent
+    #[serde(skip_deserializing)]
+    reasons: Vec<String>,
+
+    /// Equipment that is out of service, these are only sent to users who asked for that kind of outage at one of the [`Incident::stops`]
+    #[serde(default)]
+    outage: Option<Outage>,
+
+    /// ID of the agency that reported the incident, matched against [`agency::Model::id`](database::agency::Model::id)
+    #[serde(default)]
+    agency: Option<String>,
+}
+
+/// How serious an incident is, ordered from least to most severe
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Minor,
+    Moderate,
+    Severe,
+    Extreme,
+}
+
+/// How soon riders need to act, as in the Common Alerting Protocol, ordered from least to most urgent
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    Past,
+    Future,
+    Expected,
+    Immediate,
+}
+
+/// Accessibility equipment that riders can ask to be told about when it is out of service
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Outage {
+    Elevator,
+    Escalator,
 }
 
 impl<D: AsRef<str>> Incident<D> {
     pub fn new(timestamp: DateTime<Utc>, description: D) -> Self {
         Incident {
-            timestamp: timestamp,
-            description: description,
+            timestamp,
+            description,
+            id: None,
+            kind: None,
+            lines: Vec::new(),
+            severity: None,
+            urgency: None,
+            stops: Vec::new(),
+            routes: Vec::new(),
+            expires: None,
+            affected: Default::default(),
+            reasons: Vec::new(),
+            outage: None,
+            agency: None,
         }
     }
 
-    /// Checks if the incident message mentions any of the stations in the list
-    fn mentions(&self, stations: &Vec<impl AsRef<str>>) -> bool {
-        stations
-            .into_iter()
-            .any(|station| self.description.as_ref().contains(station.as_ref()))
+    pub fn with_id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    pub fn with_kind(mut self, kind: impl Into<String>) -> Self {
+        self.kind = Some(kind.into());
+        self
     }
-}
 
-/// Reads the old timestamp value from and writes a new one to the file
-async fn fetch_and_update_timestamp(path: impl AsRef<Path>) -> Result<DateTime<Utc>> {
-    use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+    pub fn with_lines(mut self, lines: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.lines = lines.into_iter().map(Into::into).collect();
+        self
+    }
 
-    let mut file = tokio::fs::File::options()
-        .read(true)
-        .write(true)
-        .open(path)
-        .await?;
-    let mut buffer = String::new();
-    file.read_to_string(&mut buffer).await?;
-    let rewind = file.rewind();
-
-    let current_time = Utc::now().to_rfc3339();
-    rewind.await?;
-    let write = file.write_all(current_time.as_bytes());
-    let result = DateTime::parse_from_rfc3339(&buffer)?.to_utc();
-    write.await?;
-    Ok(result)
+    pub fn with_severity(mut self, severity: Severity) -> Self {
+        self.severity = Some(severity);
+        self
+    }
+
+    pub fn with_urgency(mut self, urgency: Urgency) -> Self {
+        self.urgency = Some(urgency);
+        self
+    }
+
+    pub fn with_stops(mut self, stops: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.stops = stops.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_routes(mut self, routes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.routes = routes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_expires(mut self, expires: DateTime<Utc>) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    pub fn with_outage(mut self, outage: Outage) -> Self {
+        self.outage = Some(outage);
+        self
+    }
+
+    pub fn with_agency(mut self, agency: impl Into<String>) -> Self {
+        self.agency = Some(agency.into());
+        self
+    }
+
+    /// Identifies the incident across runs, which is the source's ID if it has one and a hash of the message otherwise,
+    /// prefixed with the agency since agencies reuse the same IDs, e.g. GTFS-Realtime entity IDs like "1".
+    /// The timestamp is left out of the hash since some sources change it whenever an incident is edited.
+    fn key(&self) -> String {
+        use sha2::{Digest, Sha256};
+
+        let key = match &self.id {
+            Some(id) => id.clone(),
+            None => format!("{:x}", Sha256::digest(self.description.as_ref())),
+        };
+        match &self.agency {
+            Some(agency) => format!("{agency}/{key}"),
+            None => key,
+        }
+    }
+
+    /// Checks if the bus route is listed as affected
+    fn serves(&self, route: &bus_route::Model) -> bool {
+        self.reported_by(route.agency_id.as_deref()) && self.routes.contains(&route.code)
+    }
+
+    /// Checks if the incident could be about something the agency owns, anything without an agency is shared by all of them
+    fn reported_by(&self, agency: Option<&str>) -> bool {
+        match (&self.agency, agency) {
+            (Some(reporter), Some(owner)) => reporter == owner,
+            _ => true,
+        }
+    }
 }
 
-/// Removes any incidents older than the timestamp so that users are not being sent the same messages over and over again
+/// Removes any incidents older than the timestamp, so that new users are not sent the backlog from before they signed up
 fn filter_timestamp<B: FromIterator<Incident>>(
     incidents: impl IntoIterator<Item = Incident>,
     timestamp: DateTime<Utc>,
@@ -349,6 +795,51 @@ fn filter_timestamp<B: FromIterator<Incident>>(
         .collect()
 }
 
+/// Merges incidents that were reported more than once, e.g. by several scrapers, keeping the latest version of any with the same ID
+fn dedup_incidents(incidents: impl IntoIterator<Item = Incident>) -> Vec<Incident> {
+    let mut result: Vec<Incident> = Vec::new();
+    let mut ids: HashMap<String, usize> = HashMap::new();
+    let mut contents: HashMap<_, usize> = HashMap::new();
+    for incident in incidents {
+        // Keyed on the agency as well as the ID so different agencies' incidents are never merged
+        let key = incident.id.as_ref().map(|_| incident.key());
+        if let Some(&index) = key.as_ref().and_then(|key| ids.get(key)) {
+            if incident.timestamp > result[index].timestamp {
+                result[index] = incident;
+            }
+            continue;
+        }
+        let content = (incident.timestamp, incident.description.clone());
+        match (contents.get(&content), key.as_ref()) {
+            (Some(_), None) => continue, // Identical to one that has already been seen
+            (Some(&index), Some(key)) if result[index].id.is_none() => {
+                // The same incident from a source that gave it an ID, which later versions need to be able to find
+                ids.insert(key.clone(), index);
+                result[index] = incident;
+                continue;
+            }
+            _ => {}
+        }
+        contents.entry(content).or_insert(result.len());
+        if let Some(key) = key {
+            ids.insert(key, result.len());
+        }
+        result.push(incident);
+    }
+    result
+}
+
+/// Removes any incidents that the source says are no longer in effect
+fn filter_expired<B: FromIterator<Incident>>(
+    incidents: impl IntoIterator<Item = Incident>,
+    now: DateTime<Utc>,
+) -> B {
+    incidents
+        .into_iter()
+        .filter(|incident| incident.expires.is_none_or(|expires| expires > now))
+        .collect()
+}
+
 /// Creates the template for the email
 fn create_template(index: impl AsRef<Path>) -> tera::Result<Tera> {
     let mut template = Tera::default();
@@ -356,11 +847,71 @@ fn create_template(index: impl AsRef<Path>) -> tera::Result<Tera> {
     Ok(template)
 }
 
-#[cfg_attr(test, derive(PartialEq, serde::Serialize))]
-#[derive(Debug, Deserialize)]
+/// A station along with the other spellings of its name that incidents might use
+type Aliased = (station::Model, Vec<station_alias::Model>);
+
+/// A rail line along with the stations on it
+type Line = (rail_line::Model, Vec<Aliased>);
+
+#[cfg_attr(test, derive(PartialEq))]
+#[derive(Debug)]
 struct Subscriber {
+    id: i32,
     email: Address,
-    stations: Vec<String>,
+    stations: Vec<Aliased>,
+    sent: HashMap<String, sent_incident::Model>, // Keys of the incidents that have already been delivered to the user and the version they were sent
+    since: DateTime<Utc>, // Anything published before the user's first run is backlog they are never sent
+    outages: Vec<(Outage, station::Model)>, // Stations the user wants to know about broken equipment at
+    routes: Vec<bus_route::Model>,
+    lines: Vec<Line>,
+    rules: Vec<rule::Rule>,
+}
+
+impl Subscriber {
+    /// Checks if the user is subscribed to the station directly or through one of their lines
+    fn watches(&self, station: &station::Model) -> bool {
+        self.stations
+            .iter()
+            .chain(self.lines.iter().flat_map(|(_, stations)| stations))
+            .any(|(own, _)| own == station)
+    }
+
+    /// Copies the incident for the user, listing any of their rules that it matched as the reason it is being sent
+    fn explain(&self, incident: &Incident) -> Incident {
+        let mut incident = incident.clone();
+        incident.reasons = self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(&incident))
+            .map(ToString::to_string)
+            .collect();
+        incident
+    }
+}
+
+/// A new version of an incident that the user was already sent
+#[derive(Debug, Serialize)]
+struct Update {
+    previous: String,
+    incident: Incident,
+}
+
+/// An incident the user was sent which is no longer reported by the source
+#[derive(Debug, Serialize)]
+struct Resolution {
+    #[serde(skip)]
+    key: String,
+    description: String,
+    stations: Vec<String>, // The user's stations that were affected
+}
+
+/// What was delivered to a user, saved once everyone has been processed
+#[derive(Debug, Default)]
+struct Delivery {
+    user_id: i32,
+    since: DateTime<Utc>,
+    sent: Vec<sent_incident::ActiveModel>,
+    resolved: Vec<String>,
 }
 
 // trait DatabaseFuture = Future<Output = std::result::Result<impl ConnectionTrait, DbErr>>;
@@ -376,33 +927,315 @@ impl<C: ConnectionTrait, T: Future<Output = std::result::Result<C, DbErr>>> Data
 {
 }
 
-// I only need access to the database in this function hence why I am asking for a future for the database connection rather than for the connection directly.
-// Doing it this way also makes it so that the initial connection request is sent early and so it be processing or already done by the time this function is called.
-// Plus this allows me to setup the tables for an in-memory SQLite database for testing before calling this function.
-/// Fetches all of the users with the stations that they are subscribed to from the SQL database
+/// Fetches all of the users with the stations, lines, bus routes, and rules that they are subscribed to and the incidents they were already sent from the SQL database.
+/// The aliases and lines are taken from the ones already fetched for everyone rather than fetched again for each user.
 async fn fetch_users<C: ConnectionTrait>(
-    database: impl DatabaseFuture<C>,
+    db: &C,
+    watermark: DateTime<Utc>,
+    all_stations: &[Aliased],
+    all_lines: &[Line],
 ) -> Result<Vec<Subscriber>> {
     use sea_orm::{EntityTrait, ModelTrait};
 
-    let db = &database.await?;
+    let aliases: HashMap<_, _> = all_stations
+        .iter()
+        .map(|(station, aliases)| (station.id, aliases))
+        .collect();
+    let all_lines: HashMap<_, _> = all_lines.iter().map(|line| (line.0.id, line)).collect();
     let users = User::find().all(db).await?;
     let mut subscribers = Vec::with_capacity(users.len()); // Trying to get rid of the unnecessary `mut` just makes things messy
     for user in users {
+        let mut stations = Vec::new();
+        let mut outages = Vec::new();
+        for (subscription, station) in user
+            .find_related(UserStation)
+            .find_also_related(Station)
+            .all(db)
+            .await?
+        {
+            let Some(station) = station else { continue };
+            if subscription.elevator_outages {
+                outages.push((Outage::Elevator, station.clone()));
+            }
+            if subscription.escalator_outages {
+                outages.push((Outage::Escalator, station.clone()));
+            }
+            let aliases = aliases.get(&station.id).map(|aliases| aliases.to_vec());
+            stations.push((station, aliases.unwrap_or_default()));
+        }
+        let lines = user
+            .find_related(UserLine)
+            .all(db)
+            .await?
+            .into_iter()
+            .filter_map(|subscription| all_lines.get(&subscription.line_id))
+            .map(|&line| line.clone())
+            .collect();
+        let mut rules = Vec::new();
+        for rule in user.find_related(UserRule).all(db).await? {
+            // Rules are checked when they are saved, so this only happens if one was written to the database directly
+            match rule::Rule::new(rule) {
+                Ok(rule) => rules.push(rule),
+                Err(error) => {
+                    #[cfg(feature = "log")]
+                    log::warn!("Skipping a rule for {}: {error}", user.email);
+                    #[cfg(not(feature = "log"))]
+                    eprintln!("Skipping a rule for {}: {error}", user.email);
+                }
+            }
+        }
         subscribers.push(Subscriber {
+            id: user.id,
             email: user.email.parse()?,
-            stations: user
-                .find_related(Station)
+            stations,
+            sent: user
+                .find_related(SentIncident)
                 .all(db)
                 .await?
                 .into_iter()
-                .map(|station| station.name)
+                .map(|sent| (sent.incident.clone(), sent))
                 .collect(),
+            // New users start from the latest run rather than being sent the whole backlog
+            since: user.since.unwrap_or(watermark),
+            outages,
+            routes: user.find_related(BusRoute).all(db).await?,
+            lines,
+            rules,
         })
     }
     Ok(subscribers)
 }
 
+/// Remembers which version of each incident was delivered so it is not sent to the same user again, forgets the ones that were resolved,
+/// and fixes where a new user's backlog ends
+///
+/// There is no per-user delivery time, a user whose send failed catches up because nothing was recorded in [`SentIncident`] for them
+async fn record_delivery<C: ConnectionTrait>(db: &C, delivery: Delivery) -> Result<()> {
+    use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, sea_query::Expr, sea_query::OnConflict};
+
+    if !delivery.sent.is_empty() {
+        SentIncident::insert_many(delivery.sent)
+            .on_conflict(
+                OnConflict::columns([
+                    sent_incident::Column::UserId,
+                    sent_incident::Column::Incident,
+                ])
+                .update_columns([
+                    sent_incident::Column::Description,
+                    sent_incident::Column::SentAt,
+                    sent_incident::Column::SeenAt,
+                    sent_incident::Column::Agency,
+                ])
+                .to_owned(),
+            )
+            .exec_without_returning(db)
+            .await?;
+    }
+    if !delivery.resolved.is_empty() {
+        // If the incident comes back it is a new one as far as the user is concerned
+        SentIncident::delete_many()
+            .filter(sent_incident::Column::UserId.eq(delivery.user_id))
+            .filter(sent_incident::Column::Incident.is_in(delivery.resolved))
+            .exec(db)
+            .await?;
+    }
+    User::update_many()
+        .col_expr(
+            user::Column::Since,
+            Expr::col(user::Column::Since).if_null(delivery.since),
+        )
+        .filter(user::Column::Id.eq(delivery.user_id))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// Fetches the codes that stations are listed under besides [`station::Model::code`]
+async fn fetch_station_codes<C: ConnectionTrait>(db: &C) -> Result<Vec<station_code::Model>> {
+    use sea_orm::EntityTrait;
+
+    Ok(StationCode::find().all(db).await?)
+}
+
+/// Fetches the stations that each incident was affecting as of the previous run
+async fn fetch_active<C: ConnectionTrait>(db: &C) -> Result<HashMap<String, Vec<station::Model>>> {
+    use sea_orm::EntityTrait;
+
+    let mut active: HashMap<_, Vec<_>> = HashMap::new();
+    for (incident, station) in ActiveIncident::find()
+        .find_also_related(Station)
+        .all(db)
+        .await?
+    {
+        active.entry(incident.incident).or_default().extend(station);
+    }
+    Ok(active)
+}
+
+/// Fetches every station with its aliases, whether or not anyone is subscribed to it
+async fn fetch_stations<C: ConnectionTrait>(db: &C) -> Result<Vec<Aliased>> {
+    use sea_orm::EntityTrait;
+
+    Ok(Station::find()
+        .find_with_related(StationAlias)
+        .all(db)
+        .await?)
+}
+
+/// Fetches the stations on the line in order along with their aliases
+async fn fetch_line<C: ConnectionTrait>(db: &C, line: rail_line::Model) -> Result<Line> {
+    use sea_orm::{ModelTrait, QueryOrder};
+
+    let stations = line
+        .find_related(Station)
+        .order_by_asc(line_station::Column::Sequence)
+        .find_with_related(StationAlias)
+        .all(db)
+        .await?;
+    Ok((line, stations))
+}
+
+/// Fetches every line with its stations, whether or not anyone is subscribed to it
+async fn fetch_lines<C: ConnectionTrait>(db: &C) -> Result<Vec<Line>> {
+    use sea_orm::EntityTrait;
+
+    let mut lines = Vec::new();
+    for line in RailLine::find().all(db).await? {
+        lines.push(fetch_line(db, line).await?);
+    }
+    Ok(lines)
+}
+
+/// Fills in the stations along each line between the ends of any "between X and Y" or "from X to Y" phrase in the descriptions,
+/// so users subscribed to a station in the middle are sent the incident too
+fn expand_segments(incidents: &mut [Incident], lines: &[Line]) {
+    // The stations of each line only need to be split into words once for all of the incidents
+    let places: Vec<Vec<_>> = lines
+        .iter()
+        .map(|(_, stations)| {
+            stations
+                .iter()
+                .map(|(station, aliases)| {
+                    text::spellings(
+                        std::iter::once(station.name.as_str())
+                            .chain(aliases.iter().map(|alias| alias.name.as_str())),
+                    )
+                })
+                .collect()
+        })
+        .collect();
+    for incident in incidents {
+        let words = text::words(incident.description.as_ref());
+        for ((line, stations), places) in lines.iter().zip(&places) {
+            if !incident.reported_by(line.agency_id.as_deref()) {
+                continue;
+            }
+            for (start, end) in text::segments(&words, places) {
+                let between = &stations[start.min(end)..=start.max(end)];
+                incident
+                    .affected
+                    .stations
+                    .extend(between.iter().map(|(station, _)| station.id));
+            }
+        }
+    }
+}
+
+/// Pairs each incident currently in the feed with the stations it affects
+fn find_active(
+    incidents: &[Incident],
+    seen_at: DateTime<Utc>,
+) -> Vec<active_incident::ActiveModel> {
+    use sea_orm::ActiveValue;
+
+    incidents
+        .iter()
+        .flat_map(|incident| {
+            incident
+                .affected
+                .stations
+                .iter()
+                .map(|station| active_incident::ActiveModel {
+                    incident: ActiveValue::Set(incident.key()),
+                    station_id: ActiveValue::Set(*station),
+                    description: ActiveValue::Set(incident.description.clone()),
+                    agency: ActiveValue::Set(incident.agency.clone()),
+                    seen_at: ActiveValue::Set(seen_at),
+                })
+        })
+        .collect()
+}
+
+/// Replaces the incidents that were active in the previous run with the ones from this run,
+/// only forgetting the ones from the agency in scope if there is one
+async fn update_active<C: ConnectionTrait>(
+    db: &C,
+    present: &HashSet<String>,
+    previously_active: impl IntoIterator<Item = String>,
+    active: Vec<active_incident::ActiveModel>,
+    scope: Option<&str>,
+) -> Result<()> {
+    use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, sea_query::OnConflict};
+
+    let gone: Vec<_> = previously_active
+        .into_iter()
+        .filter(|key| !present.contains(key))
+        .collect();
+    if !gone.is_empty() {
+        let mut delete =
+            ActiveIncident::delete_many().filter(active_incident::Column::Incident.is_in(gone));
+        if let Some(agency) = scope {
+            delete = delete.filter(active_incident::Column::Agency.eq(agency));
+        }
+        delete.exec(db).await?;
+    }
+    if !active.is_empty() {
+        ActiveIncident::insert_many(active)
+            .on_conflict(
+                OnConflict::columns([
+                    active_incident::Column::Incident,
+                    active_incident::Column::StationId,
+                ])
+                .update_columns([
+                    active_incident::Column::Description,
+                    active_incident::Column::Agency,
+                    active_incident::Column::SeenAt,
+                ])
+                .to_owned(),
+            )
+            .exec_without_returning(db)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Marks the sent incidents that are still being reported as seen, and forgets any sent or active incidents that have not been seen for [`FORGET_AFTER`]
+async fn forget_stale<C: ConnectionTrait>(
+    db: &C,
+    present: &HashSet<String>,
+    now: DateTime<Utc>,
+) -> Result<()> {
+    use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, sea_query::Expr};
+
+    if !present.is_empty() {
+        SentIncident::update_many()
+            .col_expr(sent_incident::Column::SeenAt, Expr::value(now))
+            .filter(sent_incident::Column::Incident.is_in(present.iter().cloned()))
+            .exec(db)
+            .await?;
+    }
+    let cutoff = now - FORGET_AFTER;
+    SentIncident::delete_many()
+        .filter(sent_incident::Column::SeenAt.lt(cutoff))
+        .exec(db)
+        .await?;
+    ActiveIncident::delete_many()
+        .filter(active_incident::Column::SeenAt.lt(cutoff))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
 // Pre-creates as much of the message as I can before copying it to be finished for each user
 fn create_message_builder(username: Option<String>, address: Address) -> message::MessageBuilder {
     let mailbox = message::Mailbox::new(username, address);
@@ -426,52 +1259,164 @@ fn create_transport(
 }
 
 /// Render the message body for the email to be sent to the user
-fn render_message(incidents: &impl Serialize, template: impl AsRef<Tera>) -> tera::Result<String> {
+fn render_message(
+    incidents: &impl Serialize,
+    updates: &impl Serialize,
+    resolutions: &impl Serialize,
+    template: impl AsRef<Tera>,
+) -> tera::Result<String> {
     let mut context = tera::Context::new();
     context.insert(stringify!(incidents), incidents);
+    context.insert(stringify!(updates), updates);
+    context.insert(stringify!(resolutions), resolutions);
     template.as_ref().render(TEMPLATE, &context)
 }
 
-/// Business logic for each individual user
-async fn process<I: IntoIterator<Item = Incident> + Clone>(
-    incidents: impl AsRef<I>,
+/// Business logic for each individual user, returns what was delivered to be saved once everyone has been processed
+async fn process(
+    incidents: impl AsRef<[Incident]>,
+    resolved: impl AsRef<HashMap<String, Vec<station::Model>>>,
     user: Subscriber,
     template: impl AsRef<Tera>,
     message_builder: impl AsRef<message::MessageBuilder>,
     transport: Arc<impl AsyncTransport<Error: Debug> + Send + Sync>,
-) -> Result<()> {
+) -> Result<Delivery> {
     #[cfg(feature = "log")]
     log::debug!("{user:?}");
 
-    let incidents: Vec<_> = filter_stations(incidents.as_ref().clone(), &user.stations);
-    if incidents.is_empty() {
-        // None of the stations the user is subscribed to have notices
+    // What was sent decides what is new rather than the timestamps, which can be late, skewed, or backdated by the source
+    let (incidents, updates) = split_sent(
+        filter_subscribed::<Vec<_>>(incidents.as_ref(), &user)
+            .into_iter()
+            .map(|incident| user.explain(incident)),
+        &user.sent,
+    );
+    let incidents: Vec<_> = filter_timestamp(incidents, user.since);
+    let resolutions: Vec<_> = user
+        .sent
+        .iter()
+        .filter_map(|(key, sent)| {
+            let stations = resolved.as_ref().get(key)?;
+            Some(Resolution {
+                key: key.clone(),
+                description: sent.description.clone(),
+                stations: stations
+                    .iter()
+                    .filter(|station| user.watches(station))
+                    .map(|station| station.name.clone())
+                    .collect(),
+            })
+        })
+        .collect();
+    if incidents.is_empty() && updates.is_empty() && resolutions.is_empty() {
+        // None of the stations the user is subscribed to have new notices
         #[cfg(feature = "log")]
         log::debug!("{}: None", user.email);
 
-        Ok(())
+        Ok(Delivery {
+            user_id: user.id,
+            since: user.since,
+            ..Default::default()
+        })
     } else {
-        let body = render_message(&incidents, template)?;
+        let body = render_message(&incidents, &updates, &resolutions, template)?;
 
         #[cfg(feature = "log")]
         log::debug!("{}: {body}", user.email);
 
-        let message = message_builder.as_ref().clone().to(user.email.into()).body(body)?;
+        let mut message_builder = message_builder.as_ref().clone();
+        if incidents.is_empty() {
+            // Only news about incidents the user already knows about
+            message_builder = message_builder.subject(if updates.is_empty() {
+                "Transit Notification All Clear"
+            } else {
+                "Transit Notification Update"
+            });
+        }
+        let message = message_builder.to(user.email.into()).body(body)?;
         match transport.send(message).await {
-            Ok(_) => Ok(()),
+            Ok(_) => {
+                use sea_orm::ActiveValue;
+
+                let sent_at = Utc::now();
+                Ok(Delivery {
+                    user_id: user.id,
+                    since: user.since,
+                    sent: incidents
+                        .iter()
+                        .chain(updates.iter().map(|update| &update.incident))
+                        .map(|incident| sent_incident::ActiveModel {
+                            user_id: ActiveValue::Set(user.id),
+                            incident: ActiveValue::Set(incident.key()),
+                            description: ActiveValue::Set(incident.description.clone()),
+                            sent_at: ActiveValue::Set(sent_at),
+                            seen_at: ActiveValue::Set(sent_at),
+                            agency: ActiveValue::Set(incident.agency.clone()),
+                        })
+                        .collect(),
+                    resolved: resolutions
+                        .into_iter()
+                        .map(|resolution| resolution.key)
+                        .collect(),
+                })
+            }
             Err(error) => Err(Error::SendError(format!("{error:?}"))),
         }
     }
 }
 
-/// Only keeps the notices for stations that the user is subscribed to
-fn filter_stations<B: FromIterator<Incident>>(
+/// Splits the incidents into ones the user has never been sent and ones that were edited since they were sent, dropping any that are unchanged
+fn split_sent(
     incidents: impl IntoIterator<Item = Incident>,
-    stations: &Vec<impl AsRef<str>>,
+    sent: &HashMap<String, sent_incident::Model>,
+) -> (Vec<Incident>, Vec<Update>) {
+    let mut new = Vec::new();
+    let mut updates = Vec::new();
+    for incident in incidents {
+        match sent.get(&incident.key()) {
+            None => new.push(incident),
+            Some(previous) if previous.description != incident.description => {
+                updates.push(Update {
+                    previous: previous.description.clone(),
+                    incident,
+                })
+            }
+            Some(_) => {} // Already delivered
+        }
+    }
+    (new, updates)
+}
+
+/// Only keeps the notices for the stations, lines, and bus routes that the user is subscribed to, the outages they asked for,
+/// and anything matching one of their rules.
+/// The incidents need to have been through the [`matcher::Matcher`] first so this is just a set intersection.
+fn filter_subscribed<'a, B: FromIterator<&'a Incident>>(
+    incidents: impl IntoIterator<Item = &'a Incident>,
+    user: &Subscriber,
 ) -> B {
+    let stations: HashSet<_> = user
+        .stations
+        .iter()
+        .chain(user.lines.iter().flat_map(|(_, stations)| stations))
+        .map(|(station, _)| station.id)
+        .collect();
+    let lines: HashSet<_> = user.lines.iter().map(|(line, _)| line.id).collect();
     incidents
         .into_iter()
-        .filter(|incident| incident.mentions(stations))
+        .filter(|incident| {
+            user.rules.iter().any(|rule| rule.matches(incident))
+                || match incident.outage {
+                    // Matched on station codes alone since the description is about the equipment rather than the station
+                    Some(outage) => user.outages.iter().any(|(kind, station)| {
+                        *kind == outage && incident.affected.stops.contains(&station.id)
+                    }),
+                    None => {
+                        !stations.is_disjoint(&incident.affected.stations)
+                            || !lines.is_disjoint(&incident.affected.lines)
+                            || user.routes.iter().any(|route| incident.serves(route))
+                    }
+                }
+        })
         .collect()
 }
 
@@ -481,30 +1426,6 @@ mod test {
 
     use super::Incident;
 
-    #[tokio::test]
-    async fn test_fetch_and_update_timestamp() {
-        use tokio::fs;
-        use tokio::io::AsyncWriteExt;
-
-        let path = "timestamp.tmp";
-        let timestamp = Utc::now();
-        fs::File::options()
-            .write(true)
-            .create_new(true)
-            .open(path)
-            .await
-            .unwrap()
-            .write_all(timestamp.clone().to_rfc3339().as_bytes())
-            .await
-            .unwrap();
-        let result = super::fetch_and_update_timestamp(path).await;
-        match fs::remove_file(path).await {
-            Ok(_) => {}
-            Err(error) => eprintln!("Failed to remove {path}: {error}"),
-        }
-        assert_eq!(result.unwrap(), timestamp);
-    }
-
     #[test]
     fn test_filter_on_timestamp() {
         use chrono::TimeZone;
@@ -519,11 +1440,455 @@ mod test {
         assert_eq!(results.pop(), None);
     }
 
+    #[test]
+    fn test_incident_fields() {
+        use super::{Severity, Urgency};
+        use chrono::TimeZone;
+
+        let timestamp = Utc.with_ymd_and_hms(2010, 7, 29, 18, 21, 28).unwrap();
+        let json = r#"{
+            "timestamp": "2010-07-29T18:21:28Z",
+            "description": "Red Line delays",
+            "id": "3754F8B2-A0A6-494E-A4B5-82C9E72DFA74",
+            "type": "Delay",
+            "lines": ["RD"],
+            "severity": "moderate",
+            "urgency": "expected"
+        }"#;
+        let expected = Incident::new(timestamp, String::from("Red Line delays"))
+            .with_id("3754F8B2-A0A6-494E-A4B5-82C9E72DFA74")
+            .with_kind("Delay")
+            .with_lines(["RD"])
+            .with_severity(Severity::Moderate)
+            .with_urgency(Urgency::Expected);
+        let incident: Incident = serde_json::from_str(json).unwrap();
+        assert_eq!(incident, expected);
+
+        // The structured fields are optional so older inputs still parse
+        let json = r#"{"timestamp": "2010-07-29T18:21:28Z", "description": "Red Line delays"}"#;
+        let incident: Incident = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            incident,
+            Incident::new(timestamp, String::from("Red Line delays"))
+        );
+    }
+
+    #[test]
+    fn test_dedup_incidents() {
+        use chrono::TimeZone;
+
+        let first = Utc.with_ymd_and_hms(2026, 1, 5, 14, 0, 0).unwrap();
+        let second = Utc.with_ymd_and_hms(2026, 1, 5, 15, 0, 0).unwrap();
+        let hello = Incident::new(first, String::from("Hello there"));
+        let original = Incident::new(first, String::from("Red Line delays")).with_id("1");
+        let edited = Incident::new(second, String::from("Red Line delays clearing")).with_id("1");
+        let results = super::dedup_incidents([
+            hello.clone(),
+            original,
+            hello.clone(),
+            edited.clone(),
+            Incident::new(second, String::from("Red Line delays clearing")).with_id("1"),
+        ]);
+        assert_eq!(results, [hello, edited.clone()]);
+
+        // One scraper did not give the incident an ID but another did, so the edit still replaces it
+        let plain = Incident::new(first, String::from("Red Line delays"));
+        let original = plain.clone().with_id("1");
+        let results = super::dedup_incidents([plain, original, edited.clone()]);
+        assert_eq!(results, [edited]);
+    }
+
+    #[test]
+    fn test_filter_expired() {
+        use chrono::TimeZone;
+
+        let now = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let open = Incident::new(now, String::from("Open ended"));
+        let active = Incident::new(now, String::from("Active"))
+            .with_expires(Utc.with_ymd_and_hms(2000, 1, 2, 0, 0, 0).unwrap());
+        let expired = Incident::new(now, String::from("Expired"))
+            .with_expires(Utc.with_ymd_and_hms(1999, 12, 31, 0, 0, 0).unwrap());
+        let results: Vec<_> = super::filter_expired([open.clone(), active.clone(), expired], now);
+        assert_eq!(results, [open, active]);
+    }
+
+    /// Subscriber to the given stations without any aliases, outages, and bus routes who has never been sent anything
+    fn subscriber(
+        stations: Vec<super::station::Model>,
+        outages: Vec<(super::Outage, super::station::Model)>,
+        routes: Vec<super::bus_route::Model>,
+    ) -> super::Subscriber {
+        super::Subscriber {
+            id: 1,
+            email: lettre::Address::new("obiwan.konobi", "jedi.com").unwrap(),
+            stations: stations
+                .into_iter()
+                .map(|station| (station, Vec::new()))
+                .collect(),
+            sent: Default::default(),
+            since: chrono::DateTime::<Utc>::MIN_UTC,
+            outages,
+            routes,
+            lines: Vec::new(),
+            rules: Vec::new(),
+        }
+    }
+
+    /// Runs the incidents through a matcher for everything the user is subscribed to before filtering them,
+    /// clearing what the matcher found so the results can be compared with the originals
+    fn filter(
+        incidents: impl IntoIterator<Item = Incident>,
+        user: &super::Subscriber,
+    ) -> Vec<Incident> {
+        let stations: Vec<_> = user
+            .stations
+            .iter()
+            .chain(user.lines.iter().flat_map(|(_, stations)| stations))
+            .cloned()
+            .collect();
+        let matcher = super::matcher::Matcher::new(&stations, &[], &user.lines).unwrap();
+        let incidents: Vec<_> = incidents
+            .into_iter()
+            .map(|mut incident| {
+                matcher.apply(&mut incident);
+                incident
+            })
+            .collect();
+        super::filter_subscribed::<Vec<_>>(&incidents, user)
+            .into_iter()
+            .map(|incident| Incident {
+                affected: Default::default(),
+                ..incident.clone()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_filter_stations() {
+        use super::{Outage, station};
+
+        let station = station::Model {
+            id: 1,
+            name: String::from("Dupont Circle"),
+            code: Some(String::from("A03")),
+            agency_id: None,
+        };
+        let named = Incident::new(Utc::now(), String::from("Escalator at Dupont Circle"));
+        let coded = Incident::new(Utc::now(), String::from("Single tracking")).with_stops(["A03"]);
+        let other = Incident::new(Utc::now(), String::from("Elevator at Farragut North"))
+            .with_stops(["A02"]);
+        let elevator = Incident::new(Utc::now(), String::from("Elevator out of service"))
+            .with_stops(["A03"])
+            .with_outage(Outage::Elevator);
+        let escalator = Incident::new(Utc::now(), String::from("Escalator out of service"))
+            .with_stops(["A03"])
+            .with_outage(Outage::Escalator);
+        let incidents = [
+            named.clone(),
+            coded.clone(),
+            other,
+            elevator.clone(),
+            escalator,
+        ];
+
+        let user = subscriber(vec![station.clone()], Vec::new(), Vec::new());
+        let results = filter(incidents.clone(), &user);
+        assert_eq!(results, [named.clone(), coded.clone()]);
+
+        // Outages are only sent to those who asked for that kind at that station
+        let outages = vec![(Outage::Elevator, station.clone())];
+        let user = subscriber(vec![station], outages, Vec::new());
+        let results = filter(incidents, &user);
+        assert_eq!(results, [named, coded, elevator]);
+    }
+
+    #[test]
+    fn test_filter_transfer_outages() {
+        use super::{Outage, station, station_code};
+
+        let station = station::Model {
+            id: 1,
+            name: String::from("Metro Center"),
+            code: Some(String::from("A01")),
+            agency_id: None,
+        };
+        let codes = [station_code::Model {
+            station_id: 1,
+            code: String::from("C01"),
+        }];
+        let upper = Incident::new(Utc::now(), String::from("Elevator out of service"))
+            .with_stops(["A01"])
+            .with_outage(Outage::Elevator);
+        let lower = Incident::new(Utc::now(), String::from("Elevator out of service"))
+            .with_stops(["C01"])
+            .with_outage(Outage::Elevator);
+        let mut incidents = [upper, lower];
+
+        let stations = [(station.clone(), Vec::new())];
+        let matcher = super::matcher::Matcher::new(&stations, &codes, &[]).unwrap();
+        for incident in &mut incidents {
+            matcher.apply(incident);
+        }
+        let user = subscriber(Vec::new(), vec![(Outage::Elevator, station)], Vec::new());
+        let results: Vec<_> = super::filter_subscribed(&incidents, &user);
+        assert_eq!(results.len(), 2);
+    }
+
+    #[test]
+    fn test_filter_station_names() {
+        use super::station;
+
+        let station = |id, name: &str| station::Model {
+            id,
+            name: String::from(name),
+            code: None,
+            agency_id: None,
+        };
+        let powerful = Incident::new(Utc::now(), String::from("A powerful storm is passing"));
+        let greeting = Incident::new(Utc::now(), String::from("hello there"));
+
+        // Substring matching sent the first to "power" and missed the second for "Hello"
+        let user = subscriber(
+            vec![station(1, "power"), station(2, "Hello")],
+            Vec::new(),
+            Vec::new(),
+        );
+        let results = filter([powerful, greeting.clone()], &user);
+        assert_eq!(results, [greeting]);
+    }
+
+    #[test]
+    fn test_filter_aliases() {
+        use super::{station, station_alias};
+
+        let station = station::Model {
+            id: 1,
+            name: String::from("Gallery Pl-Chinatown"),
+            code: None,
+            agency_id: None,
+        };
+        let aliases = ["Gallery Place", "Chinatown"].map(|name| station_alias::Model {
+            station_id: 1,
+            name: String::from(name),
+        });
+        let incidents = [
+            "Delays at Gallery Pl-Chinatown",
+            "Escalator outage at Gallery Place",
+            "Shuttle buses serving Chinatown",
+            "Delays at Navy Yard",
+        ]
+        .map(|description| Incident::new(Utc::now(), String::from(description)));
+
+        let mut user = subscriber(vec![station], Vec::new(), Vec::new());
+        user.stations[0].1 = aliases.to_vec();
+        let results = filter(incidents.clone(), &user);
+        assert_eq!(results, incidents[..3]);
+    }
+
+    #[test]
+    fn test_filter_lines() {
+        use super::{rail_line, station};
+
+        let line = rail_line::Model {
+            id: 1,
+            name: String::from("Red Line"),
+            code: Some(String::from("RD")),
+            agency_id: None,
+        };
+        let station = station::Model {
+            id: 1,
+            name: String::from("Dupont Circle"),
+            code: Some(String::from("A03")),
+            agency_id: None,
+        };
+        let coded = Incident::new(Utc::now(), String::from("Single tracking")).with_lines(["RD"]);
+        let named = Incident::new(Utc::now(), String::from("Red Line trains are delayed"));
+        let stop = Incident::new(Utc::now(), String::from("Elevator outage")).with_stops(["A03"]);
+        let on_line = Incident::new(Utc::now(), String::from("Dupont Circle is closed"));
+        let other = Incident::new(Utc::now(), String::from("Blue Line trains are delayed"))
+            .with_lines(["BL"]);
+        let incidents = [coded, named, stop, on_line, other];
+
+        let mut user = subscriber(Vec::new(), Vec::new(), Vec::new());
+        user.lines = vec![(line, vec![(station, Vec::new())])];
+        let results = filter(incidents.clone(), &user);
+        assert_eq!(results, incidents[..4]);
+    }
+
+    #[test]
+    fn test_expand_segments() {
+        use std::collections::HashSet;
+
+        use super::{rail_line, station};
+
+        let line = rail_line::Model {
+            id: 1,
+            name: String::from("Red Line"),
+            code: Some(String::from("RD")),
+            agency_id: None,
+        };
+        let stations: Vec<_> = [
+            "Metro Center",
+            "Farragut North",
+            "Dupont Circle",
+            "Woodley Park",
+        ]
+        .into_iter()
+        .zip(1..)
+        .map(|(name, id)| {
+            let station = station::Model {
+                id,
+                name: String::from(name),
+                code: None,
+                agency_id: None,
+            };
+            (station, Vec::new())
+        })
+        .collect();
+        let mut incidents = [
+            "Single tracking between Farragut North and Woodley Park",
+            "Shuttle buses from Dupont Circle to Metro Center",
+            "Delays at Farragut North and Woodley Park",
+        ]
+        .map(|description| Incident::new(Utc::now(), String::from(description)));
+        super::expand_segments(&mut incidents, &[(line, stations.clone())]);
+
+        let segments: Vec<_> = incidents
+            .iter()
+            .map(|incident| incident.affected.stations.clone())
+            .collect();
+        assert_eq!(
+            segments,
+            [
+                HashSet::from([2, 3, 4]),
+                HashSet::from([1, 2, 3]),
+                HashSet::new()
+            ]
+        );
+
+        // Someone at Dupont Circle hears about single tracking on either side of it
+        let user = subscriber(vec![stations[2].0.clone()], Vec::new(), Vec::new());
+        let results: Vec<_> = super::filter_subscribed(&incidents, &user);
+        assert_eq!(results, incidents[..2].iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_filter_rules() {
+        use super::database::{sea_orm_active_enums::RuleKind, user_rule};
+        use super::rule::Rule;
+
+        let rule = |id, kind, pattern: &str| {
+            Rule::new(user_rule::Model {
+                id,
+                user_id: 1,
+                kind,
+                pattern: String::from(pattern),
+            })
+            .unwrap()
+        };
+        let fire = Incident::new(
+            Utc::now(),
+            String::from("Fire department activity at Navy Yard"),
+        );
+        let police = Incident::new(Utc::now(), String::from("Police activity near Pentagon"));
+        let other = Incident::new(Utc::now(), String::from("Delays at Union Station"));
+
+        // Rules work anywhere in the system without being subscribed to any stations
+        let mut user = subscriber(Vec::new(), Vec::new(), Vec::new());
+        user.rules = vec![
+            rule(1, RuleKind::Keyword, "fire"),
+            rule(2, RuleKind::Regex, "(?i)smoke|police"),
+        ];
+        let results = filter([fire.clone(), police.clone(), other], &user);
+        assert_eq!(results, [fire.clone(), police]);
+
+        let mut matched = fire;
+        matched.affected.text = super::text::normalize(&matched.description).unwrap();
+        assert_eq!(user.explain(&matched).reasons, ["Keyword \"fire\""]);
+    }
+
+    #[test]
+    fn test_filter_routes() {
+        use super::bus_route;
+
+        let route = bus_route::Model {
+            id: 1,
+            code: String::from("10A"),
+            name: String::from("Hunting Point-Pentagon"),
+            agency_id: None,
+        };
+        let affected = Incident::new(Utc::now(), String::from("Detour on Washington Blvd"))
+            .with_routes(["10A", "10E"]);
+        let other =
+            Incident::new(Utc::now(), String::from("Detour on Columbia Pike")).with_routes(["16Y"]);
+
+        let user = subscriber(Vec::new(), Vec::new(), vec![route]);
+        let results = filter([affected.clone(), other], &user);
+        assert_eq!(results, [affected]);
+    }
+
+    #[tokio::test]
+    async fn test_filter_agencies() {
+        use super::database::{agency, prelude::*, station};
+        use sea_orm::{ActiveValue, EntityTrait};
+
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        super::setup_db(&db, false).await.unwrap();
+
+        let agencies = ["WMATA", "MARC"].map(|id| agency::ActiveModel {
+            id: ActiveValue::Set(String::from(id)),
+            name: ActiveValue::Set(String::from(id)),
+        });
+        Agency::insert_many(agencies)
+            .exec_without_returning(&db)
+            .await
+            .unwrap();
+        let station = |id, agency: &str| station::ActiveModel {
+            id: ActiveValue::Set(id),
+            name: ActiveValue::Set(String::from("Union Station")),
+            agency_id: ActiveValue::Set(Some(String::from(agency))),
+            ..Default::default()
+        };
+        // The same name can be used once by each agency
+        Station::insert_many([station(1, "WMATA"), station(2, "MARC")])
+            .exec_without_returning(&db)
+            .await
+            .unwrap();
+        assert!(
+            Station::insert(station(3, "WMATA"))
+                .exec(&db)
+                .await
+                .is_err()
+        );
+        // Stations without an agency are still unique by name, as they were before there were agencies
+        let unowned = |id| station::ActiveModel {
+            id: ActiveValue::Set(id),
+            name: ActiveValue::Set(String::from("Union Station")),
+            ..Default::default()
+        };
+        Station::insert(unowned(4)).exec(&db).await.unwrap();
+        assert!(Station::insert(unowned(5)).exec(&db).await.is_err());
+
+        let metro =
+            Incident::new(Utc::now(), String::from("Delays at Union Station")).with_agency("WMATA");
+        let marc = Incident::new(Utc::now(), String::from("Union Station platform change"))
+            .with_agency("MARC");
+        let unknown = Incident::new(Utc::now(), String::from("Union Station evacuated"));
+
+        let stations = Station::find_by_id(1).all(&db).await.unwrap();
+        let user = subscriber(stations, Vec::new(), Vec::new());
+        let results = filter([metro.clone(), marc, unknown.clone()], &user);
+        assert_eq!(results, [metro, unknown]);
+    }
+
     #[tokio::test]
     async fn test_fetch_users() {
         use super::{
-            Subscriber,
-            database::{prelude::*, station, user, user_station},
+            Outage, Subscriber,
+            database::{
+                line_station, prelude::*, rail_line, station, station_alias, user, user_line,
+                user_station,
+            },
             fetch_users,
         };
         use lettre::Address;
@@ -548,8 +1913,8 @@ mod test {
         let users = User::find().all(&db).await.unwrap();
         assert_eq!(users.len(), users_len);
 
-        let station_names = ["foo", "bar", "baz"].map(|name| name.to_string());
-        let stations = station_names.clone().map(|name| station::ActiveModel {
+        let station_names = ["foo", "bar", "baz"];
+        let stations = station_names.map(|name| station::ActiveModel {
             name: ActiveValue::Set(name.to_string()),
             ..Default::default()
         });
@@ -567,14 +1932,20 @@ mod test {
             user_station::ActiveModel {
                 user_id: ActiveValue::Set(users[0].id),
                 station_id: ActiveValue::Set(stations[0].id),
+                elevator_outages: ActiveValue::Set(false),
+                escalator_outages: ActiveValue::Set(false),
             },
             user_station::ActiveModel {
                 user_id: ActiveValue::Set(users[0].id),
                 station_id: ActiveValue::Set(stations[1].id),
+                elevator_outages: ActiveValue::Set(true),
+                escalator_outages: ActiveValue::Set(false),
             },
             user_station::ActiveModel {
                 user_id: ActiveValue::Set(users[2].id),
                 station_id: ActiveValue::Set(stations[2].id),
+                elevator_outages: ActiveValue::Set(false),
+                escalator_outages: ActiveValue::Set(false),
             },
         ];
         UserStation::insert_many(user_stations)
@@ -583,20 +1954,526 @@ mod test {
             .await
             .unwrap();
 
-        let subscribers = fetch_users(std::future::ready(Ok(db))).await.unwrap();
+        let alias = station_alias::Model {
+            station_id: stations[0].id,
+            name: String::from("fu"),
+        };
+        StationAlias::insert(station_alias::ActiveModel::from(alias.clone()))
+            .exec_without_returning(&db)
+            .await
+            .unwrap();
+
+        // Bob only follows a line, which goes through baz
+        let line = rail_line::Model {
+            id: 1,
+            name: String::from("Qux Line"),
+            code: Some(String::from("QX")),
+            agency_id: None,
+        };
+        RailLine::insert(rail_line::ActiveModel::from(line.clone()))
+            .exec_without_returning(&db)
+            .await
+            .unwrap();
+        LineStation::insert(line_station::ActiveModel {
+            line_id: ActiveValue::Set(line.id),
+            station_id: ActiveValue::Set(stations[2].id),
+            sequence: ActiveValue::Set(1),
+        })
+        .exec_without_returning(&db)
+        .await
+        .unwrap();
+        UserLine::insert(user_line::ActiveModel {
+            user_id: ActiveValue::Set(users[1].id),
+            line_id: ActiveValue::Set(line.id),
+        })
+        .exec_without_returning(&db)
+        .await
+        .unwrap();
+
+        let watermark = Utc::now();
+        let all_stations = super::fetch_stations(&db).await.unwrap();
+        let all_lines = super::fetch_lines(&db).await.unwrap();
+        let subscribers = fetch_users(&db, watermark, &all_stations, &all_lines)
+            .await
+            .unwrap();
         let expected: Vec<_> = [
-            vec![station_names[0].clone(), station_names[1].clone()],
-            Vec::new(),
-            vec![station_names[2].clone()],
+            (
+                vec![
+                    (stations[0].clone(), vec![alias]),
+                    (stations[1].clone(), Vec::new()),
+                ],
+                vec![(Outage::Elevator, stations[1].clone())],
+                Vec::new(),
+            ),
+            (
+                Vec::new(),
+                Vec::new(),
+                vec![(line, vec![(stations[2].clone(), Vec::new())])],
+            ),
+            (
+                vec![(stations[2].clone(), Vec::new())],
+                Vec::new(),
+                Vec::new(),
+            ),
         ]
         .into_iter()
         .zip(addresses)
-        .map(|(stations, address)| Subscriber {
+        .zip(&users)
+        .map(|(((stations, outages, lines), address), user)| Subscriber {
+            id: user.id,
             email: address,
-            stations: stations,
+            stations,
+            sent: Default::default(),
+            since: watermark,
+            outages,
+            routes: Vec::new(),
+            lines,
+            rules: Vec::new(),
         })
         .collect();
         assert_eq!(subscribers, expected);
     }
 
+    /// Runs the whole pipeline against the dummy data without actually sending anything, returning the raw messages from each run
+    async fn send_runs(
+        name: &str,
+        runs: impl IntoIterator<Item = impl super::IncidentSource>,
+    ) -> Vec<Vec<String>> {
+        use lettre::transport::stub::AsyncStubTransport;
+
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        super::setup_db(&db, true).await.unwrap();
+
+        let path = std::env::temp_dir().join(format!("fire-alarm-service-{name}.txt"));
+        tokio::fs::write(&path, "2000-01-01T00:00:00Z")
+            .await
+            .unwrap();
+        let address = lettre::Address::new("obiwan.konobi", "jedi.com").unwrap();
+
+        let mut messages = Vec::new();
+        for source in runs {
+            let transport = super::execute(
+                super::state::FileStore::new(&path),
+                std::future::ready(Ok(db.clone())),
+                source,
+                "index.html",
+                None,
+                address.clone(),
+                AsyncStubTransport::new_ok(),
+            )
+            .await
+            .unwrap();
+            messages.push(
+                transport
+                    .messages()
+                    .await
+                    .into_iter()
+                    .map(|(_, message)| message)
+                    .collect(),
+            );
+        }
+        if let Err(error) = tokio::fs::remove_file(&path).await {
+            eprintln!("Failed to remove {path:?}: {error}");
+        }
+        messages
+    }
+
+    #[test]
+    fn test_escape_message() {
+        let template = std::sync::Arc::new(super::create_template("index.html").unwrap());
+        let incidents = [Incident::new(
+            Utc::now(),
+            String::from("<script>alert('Hello there')</script>"),
+        )];
+        let body = super::render_message(&incidents, &[(); 0], &[(); 0], template).unwrap();
+        assert!(!body.contains("<script>alert"));
+        assert!(body.contains("&lt;script&gt;"));
+    }
+
+    #[tokio::test]
+    async fn test_send_once() {
+        use chrono::TimeZone;
+
+        let timestamp = Utc.with_ymd_and_hms(2026, 1, 5, 14, 0, 0).unwrap();
+        let incidents = vec![
+            Incident::new(timestamp, String::from("Hello there")),
+            Incident::new(timestamp, String::from("Hello there, delays")).with_id("1"),
+        ];
+        // Published before the first run but only reported after it, e.g. by a slow or backdating source
+        let late = Utc.with_ymd_and_hms(2026, 1, 5, 13, 0, 0).unwrap();
+        let late = Incident::new(late, String::from("Hello there, doors stuck")).with_id("2");
+        let with_late = [incidents.clone(), vec![late]].concat();
+        let messages = send_runs("test-send-once", [incidents, with_late.clone(), with_late]).await;
+
+        assert_eq!(messages.iter().map(Vec::len).collect::<Vec<_>>(), [1, 1, 0]);
+        assert!(messages[1][0].contains("Hello there, doors stuck"));
+        assert!(!messages[1][0].contains("Hello there, delays"));
+    }
+
+    #[tokio::test]
+    async fn test_send_update() {
+        use chrono::TimeZone;
+
+        let timestamp = Utc.with_ymd_and_hms(2026, 1, 5, 14, 0, 0).unwrap();
+        let original = Incident::new(timestamp, String::from("Hello there, delays")).with_id("1");
+        let timestamp = Utc.with_ymd_and_hms(2026, 1, 5, 14, 30, 0).unwrap();
+        let edited =
+            Incident::new(timestamp, String::from("Hello there, delays clearing")).with_id("1");
+        let messages = send_runs("test-send-update", [vec![original], vec![edited]]).await;
+
+        assert_eq!(messages[0].len(), 1);
+        assert!(messages[0][0].contains("Subject: Transit Notification\r\n"));
+        assert_eq!(messages[1].len(), 1);
+        assert!(messages[1][0].contains("Subject: Transit Notification Update\r\n"));
+        assert!(messages[1][0].contains("<del>Hello there, delays</del>"));
+        assert!(messages[1][0].contains("<ins>Hello there, delays clearing</ins>"));
+    }
+
+    #[tokio::test]
+    async fn test_send_edited_description() {
+        use chrono::TimeZone;
+
+        // GTFS-Realtime alerts keep the start of their active period as the timestamp however often they are edited
+        let timestamp = Utc.with_ymd_and_hms(2026, 1, 5, 14, 0, 0).unwrap();
+        let original = Incident::new(timestamp, String::from("Hello there, delays")).with_id("1");
+        let edited =
+            Incident::new(timestamp, String::from("Hello there, major delays")).with_id("1");
+        let messages = send_runs(
+            "test-send-edited-description",
+            [vec![original], vec![edited.clone()], vec![edited]],
+        )
+        .await;
+
+        assert_eq!(messages.iter().map(Vec::len).collect::<Vec<_>>(), [1, 1, 0]);
+        assert!(messages[1][0].contains("Subject: Transit Notification Update\r\n"));
+        assert!(messages[1][0].contains("<ins>Hello there, major delays</ins>"));
+    }
+
+    #[tokio::test]
+    async fn test_send_all_clear() {
+        use chrono::TimeZone;
+
+        let timestamp = Utc.with_ymd_and_hms(2026, 1, 5, 14, 0, 0).unwrap();
+        let incident = Incident::new(timestamp, String::from("Hello there, delays")).with_id("1");
+        let messages = send_runs(
+            "test-send-all-clear",
+            [vec![incident], Vec::new(), Vec::new()],
+        )
+        .await;
+
+        assert_eq!(messages.iter().map(Vec::len).collect::<Vec<_>>(), [1, 1, 0]);
+        assert!(messages[1][0].contains("Subject: Transit Notification All Clear\r\n"));
+        assert!(messages[1][0].contains("<td>Hello there, delays</td>"));
+        assert!(messages[1][0].contains("<td>Hello</td>"));
+    }
+
+    #[tokio::test]
+    async fn test_retry_failed_send() {
+        use chrono::TimeZone;
+        use lettre::transport::stub::AsyncStubTransport;
+
+        use super::StateStore;
+
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        super::setup_db(&db, true).await.unwrap();
+
+        let path = std::env::temp_dir().join("fire-alarm-service-test-retry-failed-send.txt");
+        tokio::fs::write(&path, "2000-01-01T00:00:00Z")
+            .await
+            .unwrap();
+        let address = lettre::Address::new("obiwan.konobi", "jedi.com").unwrap();
+        let timestamp = Utc.with_ymd_and_hms(2001, 1, 1, 0, 0, 0).unwrap();
+        let incidents = vec![Incident::new(timestamp, String::from("Hello there"))];
+
+        let mut runs = Vec::new();
+        for transport in [
+            AsyncStubTransport::new_error(),
+            AsyncStubTransport::new_ok(),
+        ] {
+            let transport = super::execute(
+                super::state::FileStore::new(&path),
+                std::future::ready(Ok(db.clone())),
+                incidents.clone(),
+                "index.html",
+                None,
+                address.clone(),
+                transport,
+            )
+            .await
+            .unwrap();
+            let store = super::state::FileStore::new(&path);
+            let watermark = store.fetch(&db).await.unwrap().unwrap();
+            runs.push((transport.messages().await.len(), watermark));
+        }
+        tokio::fs::remove_file(&path).await.unwrap();
+
+        // The failed run leaves the timestamp alone so the incident is sent again on the next one
+        let start = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        assert_eq!(runs[0], (1, start));
+        assert_eq!(runs[1].0, 1);
+        assert!(runs[1].1 > timestamp);
+    }
+
+    #[tokio::test]
+    async fn test_fresh_state() {
+        use lettre::transport::stub::AsyncStubTransport;
+
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        super::setup_db(&db, true).await.unwrap();
+
+        // Nothing has ever been run so there is no timestamp yet
+        let path = std::env::temp_dir().join("fire-alarm-service-test-fresh-state.txt");
+        let _ = tokio::fs::remove_file(&path).await;
+        let address = lettre::Address::new("obiwan.konobi", "jedi.com").unwrap();
+        let backlog = Incident::new(
+            Utc::now() - chrono::TimeDelta::hours(1),
+            String::from("Hello there, delays"),
+        );
+
+        let mut sent = Vec::new();
+        for run in 0..3 {
+            let mut incidents = vec![backlog.clone()];
+            if run == 2 {
+                incidents.push(Incident::new(Utc::now(), String::from("Hello there, fire")));
+            }
+            let transport = super::execute(
+                super::state::FileStore::new(&path),
+                std::future::ready(Ok(db.clone())),
+                incidents,
+                "index.html",
+                None,
+                address.clone(),
+                AsyncStubTransport::new_ok(),
+            )
+            .await
+            .unwrap();
+            sent.push(transport.messages().await.len());
+        }
+        tokio::fs::remove_file(&path).await.unwrap();
+
+        // The backlog is never sent, only what was published after the first run
+        assert_eq!(sent, [0, 0, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_user_watermarks() {
+        use chrono::TimeZone;
+        use lettre::transport::stub::AsyncStubTransport;
+        use sea_orm::{ActiveValue, EntityTrait};
+
+        use super::database::{prelude::*, user};
+
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        super::setup_db(&db, true).await.unwrap();
+
+        // Anakin's backlog starts before the incident was published so it is still sent,
+        // General Grievous only signed up after it was published so it is not
+        let caught_up = Utc.with_ymd_and_hms(2002, 1, 1, 0, 0, 0).unwrap();
+        let start = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        for (id, since) in [(1, start), (2, caught_up)] {
+            User::update(user::ActiveModel {
+                id: ActiveValue::Unchanged(id),
+                since: ActiveValue::Set(Some(since)),
+                ..Default::default()
+            })
+            .exec(&db)
+            .await
+            .unwrap();
+        }
+
+        let path = std::env::temp_dir().join("fire-alarm-service-test-user-watermarks.txt");
+        tokio::fs::write(&path, "2000-01-01T00:00:00Z")
+            .await
+            .unwrap();
+        let address = lettre::Address::new("obiwan.konobi", "jedi.com").unwrap();
+        let timestamp = Utc.with_ymd_and_hms(2001, 1, 1, 0, 0, 0).unwrap();
+        let incidents = vec![Incident::new(
+            timestamp,
+            String::from("Hello there from the high ground"),
+        )];
+
+        let transport = super::execute(
+            super::state::FileStore::new(&path),
+            std::future::ready(Ok(db.clone())),
+            incidents,
+            "index.html",
+            None,
+            address,
+            AsyncStubTransport::new_ok(),
+        )
+        .await
+        .unwrap();
+        tokio::fs::remove_file(&path).await.unwrap();
+
+        let messages = transport.messages().await;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(
+            messages[0].0.to().first().map(ToString::to_string),
+            Some(String::from("sand.hater@jedi.com"))
+        );
+        for (user, since) in User::find()
+            .all(&db)
+            .await
+            .unwrap()
+            .into_iter()
+            .zip([start, caught_up])
+        {
+            assert_eq!(user.since, Some(since));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_partial_no_all_clear() {
+        use chrono::TimeZone;
+
+        use super::source::Partial;
+
+        let timestamp = Utc.with_ymd_and_hms(2026, 1, 5, 14, 0, 0).unwrap();
+        let incident = Incident::new(timestamp, String::from("Hello there, delays")).with_id("1");
+        // A batch that only has some of the incidents says nothing about the ones it is missing
+        let messages = send_runs(
+            "test-partial-no-all-clear",
+            [Partial(vec![incident]), Partial(Vec::new())],
+        )
+        .await;
+
+        assert_eq!(messages.iter().map(Vec::len).collect::<Vec<_>>(), [1, 0]);
+    }
+
+    #[tokio::test]
+    async fn test_partial_out_of_order() {
+        use chrono::TimeZone;
+
+        use super::source::Partial;
+
+        // Each push moves everyone's delivery time up, which says nothing about when the next push's incidents were published
+        let newer = Utc.with_ymd_and_hms(2026, 1, 5, 14, 0, 0).unwrap();
+        let newer = Incident::new(newer, String::from("Hello there, delays")).with_id("1");
+        let older = Utc.with_ymd_and_hms(2026, 1, 5, 13, 0, 0).unwrap();
+        let older = Incident::new(older, String::from("Hello there, doors stuck")).with_id("2");
+        let messages = send_runs(
+            "test-partial-out-of-order",
+            [Partial(vec![newer]), Partial(vec![older])],
+        )
+        .await;
+
+        assert_eq!(messages.iter().map(Vec::len).collect::<Vec<_>>(), [1, 1]);
+        assert!(messages[1][0].contains("<td>Hello there, doors stuck</td>"));
+    }
+
+    #[tokio::test]
+    async fn test_agency_keys() {
+        use chrono::TimeZone;
+
+        let timestamp = Utc.with_ymd_and_hms(2026, 1, 5, 14, 0, 0).unwrap();
+        let metro = Incident::new(timestamp, String::from("Hello there, delays"))
+            .with_id("1")
+            .with_agency("WMATA");
+        let marc = Incident::new(timestamp, String::from("Hello there, track work"))
+            .with_id("1")
+            .with_agency("MARC");
+        assert_ne!(metro.key(), marc.key());
+        let both = super::dedup_incidents([metro.clone(), marc.clone()]);
+        assert_eq!(both, [metro.clone(), marc.clone()]);
+
+        // Another agency using the same ID is a new incident rather than an update to the first one
+        let messages =
+            send_runs("test-agency-keys", [vec![metro.clone()], vec![metro, marc]]).await;
+        assert_eq!(messages.iter().map(Vec::len).collect::<Vec<_>>(), [1, 1]);
+        assert!(messages[1][0].contains("Subject: Transit Notification\r\n"));
+        assert!(messages[1][0].contains("<td>Hello there, track work</td>"));
+    }
+
+    #[tokio::test]
+    async fn test_forget_stale() {
+        use chrono::TimeDelta;
+        use lettre::transport::stub::AsyncStubTransport;
+        use sea_orm::{ActiveValue, EntityTrait};
+
+        use super::database::{active_incident, prelude::*, sent_incident};
+        use super::source::Partial;
+
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        super::setup_db(&db, true).await.unwrap();
+
+        // Pushed sources never report that these were resolved, one was last seen long ago and the other was still being reported yesterday
+        let now = Utc::now();
+        let sent = [("1", TimeDelta::days(30)), ("2", TimeDelta::days(1))].map(|(key, age)| {
+            sent_incident::ActiveModel {
+                user_id: ActiveValue::Set(1),
+                incident: ActiveValue::Set(String::from(key)),
+                description: ActiveValue::Set(String::from("Delays at the high ground")),
+                sent_at: ActiveValue::Set(now - TimeDelta::days(30)),
+                seen_at: ActiveValue::Set(now - age),
+                agency: ActiveValue::Set(None),
+            }
+        });
+        SentIncident::insert_many(sent)
+            .exec_without_returning(&db)
+            .await
+            .unwrap();
+        ActiveIncident::insert(active_incident::ActiveModel {
+            incident: ActiveValue::Set(String::from("1")),
+            station_id: ActiveValue::Set(3),
+            description: ActiveValue::Set(String::from("Delays at the high ground")),
+            agency: ActiveValue::Set(None),
+            seen_at: ActiveValue::Set(now - TimeDelta::days(30)),
+        })
+        .exec_without_returning(&db)
+        .await
+        .unwrap();
+
+        let path = std::env::temp_dir().join("fire-alarm-service-test-forget-stale.txt");
+        let address = lettre::Address::new("obiwan.konobi", "jedi.com").unwrap();
+        let transport = super::execute(
+            super::state::FileStore::new(&path),
+            std::future::ready(Ok(db.clone())),
+            Partial(Vec::<Incident>::new()),
+            "index.html",
+            None,
+            address,
+            AsyncStubTransport::new_ok(),
+        )
+        .await
+        .unwrap();
+        tokio::fs::remove_file(&path).await.unwrap();
+
+        // Forgotten without an all clear since nothing says they were resolved
+        assert!(transport.messages().await.is_empty());
+        let sent: Vec<_> = SentIncident::find().all(&db).await.unwrap();
+        assert_eq!(
+            sent.iter()
+                .map(|sent| sent.incident.as_str())
+                .collect::<Vec<_>>(),
+            ["2"]
+        );
+        assert!(ActiveIncident::find().all(&db).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scoped_all_clear() {
+        use chrono::TimeZone;
+
+        use super::source::Scoped;
+
+        let timestamp = Utc.with_ymd_and_hms(2026, 1, 5, 14, 0, 0).unwrap();
+        let incident = Incident::new(timestamp, String::from("Hello there, delays")).with_id("1");
+        let scoped = |agency: &str, incidents| Scoped {
+            agency: Some(String::from(agency)),
+            source: incidents,
+        };
+        // Another agency not reporting the incident does not mean it has been resolved
+        let messages = send_runs(
+            "test-scoped-all-clear",
+            [
+                scoped("WMATA", vec![incident]),
+                scoped("MARC", Vec::new()),
+                scoped("WMATA", Vec::new()),
+            ],
+        )
+        .await;
+
+        assert_eq!(messages.iter().map(Vec::len).collect::<Vec<_>>(), [1, 0, 1]);
+        assert!(messages[2][0].contains("Subject: Transit Notification All Clear\r\n"));
+    }
 }
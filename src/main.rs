@@ -1,48 +1,250 @@
-use std::io;
+use std::io::{self, Read};
+use std::time::Duration;
 
-use clap::Parser;
-#[cfg(not(feature = "log"))]
-use fire_alarm_service::Args as Cli;
+use camino::Utf8PathBuf;
+use clap::{Parser, ValueEnum};
+use fire_alarm_service::source::{
+    Partial,
+    cache::HttpCache,
+    files::Files,
+    http::HttpJson,
+    ndjson::{self, Ndjson},
+};
+use fire_alarm_service::{Args, Error, Incident, IncidentSource};
 
 #[tokio::main]
 async fn main() {
-    let args = Cli::parse();
+    let cli = Cli::parse();
 
     #[cfg(feature = "log")]
-    let args = {
-        env_logger::Builder::new()
-            .filter_level(args.verbosity.log_level_filter())
-            .init();
-        args.args
-    };
+    env_logger::Builder::new()
+        .filter_level(cli.verbosity.log_level_filter())
+        .init();
 
-    let incidents: Vec<_> = serde_json::from_reader(io::BufReader::new(io::stdin()))
-        .expect("Failed to parse incidents");
+    #[cfg(feature = "webhook")]
+    if let Some(address) = cli.listen {
+        // Required by clap whenever an address is given
+        let secret = cli.webhook_secret.unwrap_or_default();
+        serve(cli.args, address, secret).await;
+        return;
+    }
+
+    #[cfg(feature = "gtfs-rt")]
+    if let Some(location) = &cli.gtfs_rt {
+        let mut source = fire_alarm_service::source::gtfs_rt::GtfsRt::new(location.clone());
+        if let Some(directory) = &cli.http_cache {
+            source = source.with_cache(HttpCache::new(directory));
+        }
+        run(&cli.args, source).await;
+        return;
+    }
+
+    if !cli.incidents.is_empty() {
+        run(&cli.args, Files::new(cli.incidents)).await;
+    } else if let Some(config) = &cli.http {
+        let mut source = HttpJson::load(config)
+            .await
+            .expect("Failed to read HTTP source config");
+        if let Some(directory) = &cli.http_cache {
+            source = source.with_cache(HttpCache::new(directory));
+        }
+        run(&cli.args, source).await;
+    } else if let InputFormat::Ndjson = cli.input_format {
+        // Sends each batch as soon as the producer pauses rather than waiting for it to finish
+        let mut stream = Ndjson::new(tokio::io::BufReader::new(tokio::io::stdin()))
+            .with_max_age(Duration::from_secs(cli.max_batch_age))
+            .with_max_size(cli.max_batch_size);
+        let idle = Duration::from_secs(cli.flush_after);
+        while let Some(incidents) = stream
+            .next_batch(idle)
+            .await
+            .expect("Failed to read incidents")
+        {
+            // One failed batch should not stop the rest of the stream from being delivered
+            if let Err(error) = try_run(&cli.args, Partial(incidents)).await {
+                #[cfg(feature = "log")]
+                log::error!("{error}");
+                #[cfg(not(feature = "log"))]
+                eprintln!("{error}");
+            }
+        }
+    } else {
+        let mut input = Vec::new();
+        io::stdin()
+            .read_to_end(&mut input)
+            .expect("Failed to read incidents");
+        let incidents = cli
+            .parse_incidents(&input)
+            .expect("Failed to parse incidents");
+        run(&cli.args, incidents).await;
+    }
+}
+
+async fn run(args: &Args, source: impl IncidentSource + Sync) {
+    try_run(args, source)
+        .await
+        .expect("Failed to run Fire-Alarm Service")
+}
 
+async fn try_run(args: &Args, source: impl IncidentSource + Sync) -> Result<(), Error> {
     fire_alarm_service::run(
-        args.timestamp,
-        sea_orm::Database::connect(args.database),
-        incidents,
+        args.state_store(),
+        sea_orm::Database::connect(args.database.clone()),
+        args.scope(source),
         &args.index,
-        args.username,
-        args.address,
-        args.password,
-        &args.relay,
+        args.smtp(),
     )
     .await
-    .expect("Failed to run Fire-Alarm Service")
 }
 
-#[cfg(feature = "log")]
+/// Runs the incidents pushed to the webhook through the service as soon as they arrive
+#[cfg(feature = "webhook")]
+async fn serve(args: Args, address: std::net::SocketAddr, secret: String) {
+    use std::sync::Arc;
+
+    let args = Arc::new(args);
+    // Batches are handled one at a time so runs do not race each other to update the state
+    let lock = Arc::new(tokio::sync::Mutex::new(()));
+    let router = fire_alarm_service::webhook::router(secret, move |incidents| {
+        let args = args.clone();
+        let lock = lock.clone();
+        async move {
+            let _guard = lock.lock().await;
+            try_run(&args, Partial(incidents)).await
+        }
+    });
+    let listener = tokio::net::TcpListener::bind(address)
+        .await
+        .expect("Failed to listen for incidents");
+    axum::serve(listener, router)
+        .await
+        .expect("Failed to serve webhook")
+}
+
+/// Send only the transit notifications that users care about
 #[derive(Parser)]
+#[command(version)]
 struct Cli {
+    /// JSON or NDJSON files, or directories of them, to read the incidents from instead of stdin
+    #[arg(long, value_name = "PATH")]
+    incidents: Vec<Utf8PathBuf>,
+
+    /// JSON file describing an HTTP API to request the incidents from instead of stdin
+    #[arg(
+        long,
+        value_name = "CONFIG",
+        conflicts_with = "incidents",
+        group = "remote"
+    )]
+    #[cfg_attr(feature = "env", arg(env))]
+    http: Option<Utf8PathBuf>,
+
+    /// File path or URL of a GTFS-Realtime feed to read the service alerts from instead of stdin
+    #[cfg(feature = "gtfs-rt")]
+    #[arg(
+        long,
+        value_name = "LOCATION",
+        conflicts_with = "incidents",
+        group = "remote"
+    )]
+    #[cfg_attr(feature = "env", arg(env))]
+    gtfs_rt: Option<fire_alarm_service::source::Location>,
+
+    /// Directory to cache the responses from the HTTP API or GTFS-Realtime feed in, so unchanged responses are not downloaded again
+    #[arg(long, value_name = "DIRECTORY", requires = "remote")]
+    #[cfg_attr(feature = "env", arg(env))]
+    http_cache: Option<Utf8PathBuf>,
+
+    /// Address to listen on for incidents pushed to `POST /incidents` instead of reading them from stdin
+    #[cfg(feature = "webhook")]
+    #[arg(
+        long,
+        value_name = "ADDRESS",
+        requires = "webhook_secret",
+        conflicts_with_all = ["incidents", "remote", "input_format"]
+    )]
+    #[cfg_attr(feature = "env", arg(env))]
+    listen: Option<std::net::SocketAddr>,
+
+    /// Shared secret that pushed incidents must be sent with, either as a bearer token or as the key of an HMAC-SHA256 signature
+    #[cfg(feature = "webhook")]
+    #[arg(long)]
+    #[cfg_attr(feature = "env", arg(env))]
+    webhook_secret: Option<String>,
+
+    /// Format of the incidents read from stdin
+    #[arg(long, value_enum, default_value_t = InputFormat::Json)]
+    #[cfg_attr(feature = "env", arg(env))]
+    input_format: InputFormat,
+
+    /// Seconds without a new line before the NDJSON incidents read so far are sent
+    #[arg(long, default_value_t = 1)]
+    #[cfg_attr(feature = "env", arg(env))]
+    flush_after: u64,
+
+    /// Seconds after the first NDJSON incident in a batch is read before the batch is sent, even if the producer has not gone quiet
+    #[arg(long, default_value_t = ndjson::DEFAULT_MAX_AGE.as_secs())]
+    #[cfg_attr(feature = "env", arg(env))]
+    max_batch_age: u64,
+
+    /// Most NDJSON incidents sent in one batch
+    #[arg(long, default_value_t = ndjson::DEFAULT_MAX_SIZE)]
+    #[cfg_attr(feature = "env", arg(env))]
+    max_batch_size: usize,
+
+    /// Timezone of the dates in a feed that do not specify one
+    #[cfg(feature = "feed")]
+    #[arg(long, default_value_t = chrono_tz::UTC)]
+    #[cfg_attr(feature = "env", arg(env))]
+    timezone: chrono_tz::Tz,
+
     #[command(flatten)]
     args: fire_alarm_service::Args,
 
+    #[cfg(feature = "log")]
+    /// Set the verbosity of logging
     #[command(flatten)]
     verbosity: clap_verbosity_flag::Verbosity,
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum InputFormat {
+    /// JSON array of incidents
+    Json,
+
+    /// One JSON incident per line, malformed lines are skipped
+    Ndjson,
+
+    /// GTFS-Realtime feed containing service alerts
+    #[cfg(feature = "gtfs-rt")]
+    GtfsRt,
+
+    /// RSS or Atom feed of service alerts
+    #[cfg(feature = "feed")]
+    Feed,
+
+    /// Common Alerting Protocol 1.2 alert
+    #[cfg(feature = "cap")]
+    Cap,
+}
+
+impl Cli {
+    fn parse_incidents(&self, input: &[u8]) -> Result<Vec<Incident>, Error> {
+        match self.input_format {
+            InputFormat::Json => Ok(serde_json::from_slice(input)?),
+            InputFormat::Ndjson => ndjson::parse(input),
+            #[cfg(feature = "gtfs-rt")]
+            InputFormat::GtfsRt => fire_alarm_service::source::gtfs_rt::GtfsRt::decode(input),
+            #[cfg(feature = "feed")]
+            InputFormat::Feed => {
+                fire_alarm_service::source::feed::Feed::parse(input, self.timezone)
+            }
+            #[cfg(feature = "cap")]
+            InputFormat::Cap => fire_alarm_service::source::cap::Cap::parse(input),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::env;
@@ -60,6 +262,63 @@ mod test {
         Ok(serde_json::from_str(&dst)?)
     }
 
+    #[cfg(feature = "gtfs-rt")]
+    #[test]
+    fn test_gtfs_rt_location() {
+        use clap::Parser;
+        use fire_alarm_service::source::Location;
+
+        let required = [
+            "fire-alarm-service",
+            "--address=obiwan.konobi@jedi.com",
+            "--password=hello-there",
+            "--relay=smtp.jedi.com",
+            "--database=sqlite::memory:",
+        ];
+        let cli = super::Cli::try_parse_from(required.into_iter().chain([
+            "--gtfs-rt=https://api.wmata.com/gtfs/rail-gtfsrt-alerts.pb",
+            "--http-cache=cache",
+        ]))
+        .unwrap();
+        assert!(matches!(cli.gtfs_rt, Some(Location::Url(_))));
+
+        // Only one remote source can be read at a time
+        assert!(
+            super::Cli::try_parse_from(
+                required
+                    .into_iter()
+                    .chain(["--gtfs-rt=alerts.pb", "--http=wmata.json"])
+            )
+            .is_err()
+        );
+    }
+
+    #[cfg(feature = "webhook")]
+    #[test]
+    fn test_listen_conflicts() {
+        use clap::Parser;
+
+        let required = [
+            "fire-alarm-service",
+            "--address=obiwan.konobi@jedi.com",
+            "--password=hello-there",
+            "--relay=smtp.jedi.com",
+            "--database=sqlite::memory:",
+            "--listen=127.0.0.1:8080",
+            "--webhook-secret=hello-there",
+        ];
+        assert!(super::Cli::try_parse_from(required).is_ok());
+
+        // Pushed incidents are the only source while listening
+        for source in [
+            "--incidents=incidents.json",
+            "--http=wmata.json",
+            "--input-format=ndjson",
+        ] {
+            assert!(super::Cli::try_parse_from(required.into_iter().chain([source])).is_err());
+        }
+    }
+
     #[tokio::test]
     async fn test_fetch_incidents() {
         let path = env::var("INCIDENTS").unwrap_or_else(|_| String::from("incidents.json"));
@@ -84,6 +343,7 @@ mod test {
         let incidents = fetch_incidents(path).await.unwrap();
 
         let timestamp = env::var("TIMESTAMP").unwrap_or_else(|_| String::from("timestamp.txt"));
+        let state = fire_alarm_service::state::FileStore::new(timestamp);
         let database = match env::var("DATABASE") {
             Ok(opt) => sea_orm::Database::connect(opt).await,
             Err(_) => {
@@ -95,7 +355,7 @@ mod test {
         let address = lettre::Address::new("obiwan.konobi", "jedi.com").unwrap();
 
         fire_alarm_service::test_run(
-            timestamp,
+            state,
             std::future::ready(database),
             incidents,
             "index.html",
@@ -112,6 +372,7 @@ mod test {
         let incidents = fetch_incidents(path).await.unwrap();
 
         let timestamp = env::var("TIMESTAMP").unwrap_or_else(|_| String::from("timestamp.txt"));
+        let state = fire_alarm_service::state::FileStore::new(timestamp);
         let database = match env::var("DATABASE") {
             Ok(opt) => sea_orm::Database::connect(opt).await,
             Err(_) => {
@@ -123,7 +384,7 @@ mod test {
         let address = lettre::Address::new("obiwan.konobi", "jedi.com").unwrap();
 
         fire_alarm_service::file_run(
-            timestamp,
+            state,
             std::future::ready(database),
             incidents,
             "index.html",
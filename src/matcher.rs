@@ -0,0 +1,196 @@
+//! Finding every station and line that each incident mentions in one pass over its description,
+//! so the cost of matching does not grow with the number of users
+
+use std::collections::{HashMap, HashSet};
+
+use aho_corasick::AhoCorasick;
+
+use crate::database::station_code;
+use crate::{Aliased, Incident, Line, Result, text};
+
+/// Something an incident can affect
+#[derive(Clone, Copy, Debug)]
+enum Target {
+    Station(i32),
+    Line(i32),
+}
+
+/// A [`Target`] along with the agency that owns it
+type Owned = (Target, Option<String>);
+
+/// The stations and lines an incident affects, filled in once per run rather than worked out again for every user
+#[derive(Clone, Debug, Default, PartialEq)]
+pub(crate) struct Affected {
+    pub(crate) stations: HashSet<i32>,
+    pub(crate) lines: HashSet<i32>,
+    pub(crate) stops: HashSet<i32>, // Stations listed by one of their codes rather than named, which is all that outages are matched on
+    pub(crate) text: String, // The description as it was normalized for matching, which the keyword rules use too
+}
+
+/// Automaton over every spelling of every station and line name, plus lookups for their codes
+pub(crate) struct Matcher {
+    automaton: AhoCorasick,
+    names: Vec<Owned>, // What each pattern in the automaton belongs to
+    stops: HashMap<String, Vec<(i32, Option<String>)>>, // Every code of every station, e.g. transfer stations have one per platform
+    lines: HashMap<String, Vec<Owned>>,
+}
+
+impl Matcher {
+    pub(crate) fn new(
+        stations: &[Aliased],
+        codes: &[station_code::Model],
+        lines: &[Line],
+    ) -> Result<Self> {
+        let mut patterns = Vec::new();
+        let mut names = Vec::new();
+        let mut stops: HashMap<_, Vec<_>> = HashMap::new();
+        let mut agencies = HashMap::new();
+        for (station, aliases) in stations {
+            let target = (Target::Station(station.id), station.agency_id.clone());
+            let spellings =
+                std::iter::once(&station.name).chain(aliases.iter().map(|alias| &alias.name));
+            for pattern in spellings.filter_map(|name| text::normalize(name)) {
+                patterns.push(pattern);
+                names.push(target.clone());
+            }
+            if let Some(code) = &station.code {
+                let stop = (station.id, station.agency_id.clone());
+                stops.entry(code.clone()).or_default().push(stop);
+            }
+            agencies.insert(station.id, &station.agency_id);
+        }
+        for code in codes {
+            // Codes for stations that are not being matched are ignored
+            if let Some(&agency) = agencies.get(&code.station_id) {
+                let stop = (code.station_id, agency.clone());
+                stops.entry(code.code.clone()).or_default().push(stop);
+            }
+        }
+
+        let mut codes: HashMap<_, Vec<_>> = HashMap::new();
+        for (line, _) in lines {
+            let target = (Target::Line(line.id), line.agency_id.clone());
+            if let Some(pattern) = text::normalize(&line.name) {
+                patterns.push(pattern);
+                names.push(target.clone());
+            }
+            if let Some(code) = &line.code {
+                codes.entry(code.clone()).or_default().push(target);
+            }
+        }
+
+        Ok(Self {
+            automaton: AhoCorasick::new(patterns)?,
+            names,
+            stops,
+            lines: codes,
+        })
+    }
+
+    /// Fills in everything the incident mentions by name or lists by code,
+    /// leaving out anything owned by a different agency than the one that reported it
+    pub(crate) fn apply(&self, incident: &mut Incident) {
+        let description = text::normalize(incident.description.as_ref()).unwrap_or_default();
+        // Overlapping since neighbouring names share the space between them
+        let named = self
+            .automaton
+            .find_overlapping_iter(&description)
+            .map(|found| &self.names[found.pattern().as_usize()]);
+        let lines = incident
+            .lines
+            .iter()
+            .filter_map(|code| self.lines.get(code))
+            .flatten();
+        let targets: Vec<_> = named.chain(lines).collect();
+        let stops: Vec<_> = incident
+            .stops
+            .iter()
+            .filter_map(|code| self.stops.get(code))
+            .flatten()
+            .filter(|(_, agency)| incident.reported_by(agency.as_deref()))
+            .map(|(id, _)| *id)
+            .collect();
+        incident.affected.stations.extend(&stops);
+        incident.affected.stops.extend(stops);
+
+        for (target, agency) in targets {
+            if !incident.reported_by(agency.as_deref()) {
+                continue;
+            }
+            match *target {
+                Target::Station(id) => incident.affected.stations.insert(id),
+                Target::Line(id) => incident.affected.lines.insert(id),
+            };
+        }
+        incident.affected.text = description;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use chrono::Utc;
+
+    use crate::Incident;
+    use crate::database::{rail_line, station, station_alias, station_code};
+
+    fn station(id: i32, name: &str, code: Option<&str>) -> station::Model {
+        station::Model {
+            id,
+            name: String::from(name),
+            code: code.map(String::from),
+            agency_id: None,
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let alias = station_alias::Model {
+            station_id: 3,
+            name: String::from("Chinatown"),
+        };
+        let stations = [
+            (station(1, "Farragut North", Some("A02")), Vec::new()),
+            (station(2, "Farragut West", None), Vec::new()),
+            (station(3, "Gallery Pl-Chinatown", None), vec![alias]),
+            (station(4, "North", None), Vec::new()),
+            (station(5, "Metro Center", Some("A01")), Vec::new()),
+        ];
+        // Metro Center is listed under a different code on the lower level
+        let codes = [station_code::Model {
+            station_id: 5,
+            code: String::from("C01"),
+        }];
+        let line = rail_line::Model {
+            id: 1,
+            name: String::from("Red Line"),
+            code: Some(String::from("RD")),
+            agency_id: None,
+        };
+        let matcher = super::Matcher::new(&stations, &codes, &[(line, Vec::new())]).unwrap();
+
+        let mut incident = Incident::new(
+            Utc::now(),
+            String::from("Red Line trains bypass farragut north and CHINATOWN"),
+        );
+        matcher.apply(&mut incident);
+        assert_eq!(incident.affected.stations, HashSet::from([1, 3, 4]));
+        assert_eq!(incident.affected.lines, HashSet::from([1]));
+
+        let mut incident = Incident::new(Utc::now(), String::from("Single tracking"))
+            .with_stops(["A02"])
+            .with_lines(["RD"]);
+        matcher.apply(&mut incident);
+        assert_eq!(incident.affected.stations, HashSet::from([1]));
+        assert_eq!(incident.affected.lines, HashSet::from([1]));
+        assert_eq!(incident.affected.stops, HashSet::from([1]));
+
+        for code in ["A01", "C01"] {
+            let mut incident =
+                Incident::new(Utc::now(), String::from("Elevator outage")).with_stops([code]);
+            matcher.apply(&mut incident);
+            assert_eq!(incident.affected.stops, HashSet::from([5]));
+        }
+    }
+}
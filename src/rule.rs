@@ -0,0 +1,195 @@
+//! Users' own keyword and regex filters, for incidents anywhere in the system rather than at their stations
+
+use std::fmt;
+
+use regex::Regex;
+
+use crate::database::{sea_orm_active_enums::RuleKind, user_rule};
+use crate::{Error, Incident, Result, text};
+
+/// A rule that has been checked and is ready to be run against the incidents
+#[derive(Debug)]
+pub(crate) struct Rule {
+    rule: user_rule::Model,
+    compiled: Compiled,
+}
+
+#[derive(Debug)]
+enum Compiled {
+    Keyword(String), // Normalized the same way as the descriptions
+    Regex(Regex),
+}
+
+impl Rule {
+    pub(crate) fn new(rule: user_rule::Model) -> Result<Self> {
+        let compiled = compile(&rule.kind, &rule.pattern)?;
+        Ok(Self { rule, compiled })
+    }
+
+    /// Keywords are matched on whole words ignoring case and punctuation like station names are,
+    /// while regexes are matched against the description as it is
+    pub(crate) fn matches(&self, incident: &Incident) -> bool {
+        match &self.compiled {
+            Compiled::Keyword(keyword) => incident.affected.text.contains(keyword),
+            Compiled::Regex(regex) => regex.is_match(incident.description.as_ref()),
+        }
+    }
+}
+
+/// Describes the rule as the reason an incident was sent
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.rule.kind {
+            RuleKind::Keyword => write!(f, "Keyword \"{}\"", self.rule.pattern),
+            RuleKind::Regex => write!(f, "Pattern /{}/", self.rule.pattern),
+        }
+    }
+}
+
+#[cfg(test)]
+impl PartialEq for Rule {
+    fn eq(&self, other: &Self) -> bool {
+        self.rule == other.rule
+    }
+}
+
+/// Checks that the rule can match something, i.e. that a keyword has some words in it and that a regex compiles
+pub(crate) fn validate(kind: &RuleKind, pattern: &str) -> Result<()> {
+    compile(kind, pattern).map(|_| ())
+}
+
+fn compile(kind: &RuleKind, pattern: &str) -> Result<Compiled> {
+    match kind {
+        RuleKind::Keyword => text::normalize(pattern)
+            .map(Compiled::Keyword)
+            .ok_or_else(|| Error::EmptyKeywordError(String::from(pattern))),
+        RuleKind::Regex => Ok(Compiled::Regex(Regex::new(pattern)?)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::Utc;
+
+    use super::{Rule, RuleKind, user_rule};
+    use crate::Incident;
+
+    fn rule(kind: RuleKind, pattern: &str) -> user_rule::Model {
+        user_rule::Model {
+            id: 1,
+            user_id: 1,
+            kind,
+            pattern: String::from(pattern),
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let mut fire = Incident::new(Utc::now(), String::from("FIRE department activity"));
+        fire.affected.text = crate::text::normalize(&fire.description).unwrap();
+        let mut fireworks = Incident::new(Utc::now(), String::from("Crowds after the fireworks"));
+        fireworks.affected.text = crate::text::normalize(&fireworks.description).unwrap();
+
+        let keyword = Rule::new(rule(RuleKind::Keyword, "Fire")).unwrap();
+        assert!(keyword.matches(&fire));
+        assert!(!keyword.matches(&fireworks));
+
+        let regex = Rule::new(rule(RuleKind::Regex, "(?i)fire(works)?")).unwrap();
+        assert!(regex.matches(&fire));
+        assert!(regex.matches(&fireworks));
+        assert_eq!(regex.to_string(), "Pattern /(?i)fire(works)?/");
+    }
+
+    #[tokio::test]
+    async fn test_validate_on_save() {
+        use crate::database::{prelude::*, user};
+        use sea_orm::{ActiveModelTrait, ActiveValue, ColumnTrait, EntityTrait, QueryFilter};
+
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        crate::setup_db(&db, false).await.unwrap();
+        User::insert(user::ActiveModel {
+            id: ActiveValue::Set(1),
+            email: ActiveValue::Set(String::from("obiwan.konobi@jedi.com")),
+            ..Default::default()
+        })
+        .exec(&db)
+        .await
+        .unwrap();
+
+        let save = |kind, pattern: &str| user_rule::ActiveModel {
+            user_id: ActiveValue::Set(1),
+            kind: ActiveValue::Set(kind),
+            pattern: ActiveValue::Set(String::from(pattern)),
+            ..Default::default()
+        };
+        assert!(
+            save(RuleKind::Keyword, "police activity")
+                .insert(&db)
+                .await
+                .is_ok()
+        );
+        assert!(save(RuleKind::Regex, "smok(e|y)").insert(&db).await.is_ok());
+        assert!(save(RuleKind::Keyword, " - ").insert(&db).await.is_err());
+        assert!(save(RuleKind::Regex, "smok(e|y").insert(&db).await.is_err());
+        assert_eq!(UserRule::find().all(&db).await.unwrap().len(), 2);
+
+        // Changing only one of the two is checked against the other as it is stored
+        let keyword = save(RuleKind::Keyword, "smok(e|y")
+            .insert(&db)
+            .await
+            .unwrap();
+        let regex = UserRule::find()
+            .filter(user_rule::Column::Kind.eq(RuleKind::Regex))
+            .one(&db)
+            .await
+            .unwrap()
+            .unwrap();
+        let update = |id, kind: Option<RuleKind>, pattern: Option<&str>| user_rule::ActiveModel {
+            id: ActiveValue::Unchanged(id),
+            kind: kind.map_or(ActiveValue::NotSet, ActiveValue::Set),
+            pattern: pattern.map_or(ActiveValue::NotSet, |pattern| {
+                ActiveValue::Set(String::from(pattern))
+            }),
+            ..Default::default()
+        };
+        assert!(
+            update(keyword.id, Some(RuleKind::Regex), None)
+                .update(&db)
+                .await
+                .is_err()
+        );
+        assert!(
+            update(regex.id, None, Some("smok(e|y"))
+                .update(&db)
+                .await
+                .is_err()
+        );
+        assert!(
+            update(regex.id, None, Some("smok(e|ing)"))
+                .update(&db)
+                .await
+                .is_ok()
+        );
+        assert!(
+            update(keyword.id, None, Some("smoke"))
+                .update(&db)
+                .await
+                .is_ok()
+        );
+        let rules: Vec<_> = UserRule::find()
+            .all(&db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|rule| (rule.kind, rule.pattern))
+            .collect();
+        assert_eq!(
+            rules,
+            [
+                (RuleKind::Keyword, String::from("police activity")),
+                (RuleKind::Regex, String::from("smok(e|ing)")),
+                (RuleKind::Keyword, String::from("smoke")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,305 @@
+//! On-disk cache for polled HTTP sources, so frequent polling stays within an agency's rate limits
+
+use std::path::PathBuf;
+
+use chrono::{DateTime, TimeDelta, Utc};
+use reqwest::{
+    RequestBuilder, StatusCode,
+    header::{self, HeaderMap, HeaderValue},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{Error, Result};
+
+/// How long to back off after being rate limited when the server does not say
+const DEFAULT_RETRY_AFTER: TimeDelta = TimeDelta::seconds(60);
+
+/// Remembers the last response from each URL so that unchanged responses are not downloaded again
+#[derive(Clone, Debug)]
+pub struct HttpCache {
+    directory: PathBuf,
+}
+
+/// What is known about the cached response from a URL
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Entry {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    retry_after: Option<DateTime<Utc>>, // No requests are sent to the URL until then
+}
+
+impl HttpCache {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        HttpCache {
+            directory: directory.into(),
+        }
+    }
+
+    /// Sends the request conditionally on the cached response having changed, returning the cached body if it has not.
+    /// While rate limited the cached body is returned without sending anything.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Vec<u8>> {
+        let (client, request) = request.build_split();
+        let mut request = request?;
+        let key = cache_key(&request);
+        let entry_path = self.directory.join(format!("{key}.json"));
+        let body_path = self.directory.join(format!("{key}.body"));
+
+        let entry: Entry = match read_optional(&entry_path).await? {
+            Some(entry) => serde_json::from_slice(&entry).unwrap_or_default(), // A corrupt entry just means starting over
+            None => Entry::default(),
+        };
+        let cached = read_optional(&body_path).await?;
+        if let Some(until) = entry.retry_after
+            && until > Utc::now()
+        {
+            return cached.ok_or(Error::RateLimitedError(until));
+        }
+
+        if cached.is_some() {
+            // The validators are useless without the body they validate
+            let headers = request.headers_mut();
+            insert_header(headers, header::IF_NONE_MATCH, entry.etag.as_deref());
+            insert_header(
+                headers,
+                header::IF_MODIFIED_SINCE,
+                entry.last_modified.as_deref(),
+            );
+        }
+        let response = client.execute(request).await?;
+        match (response.status(), cached) {
+            (StatusCode::NOT_MODIFIED, Some(cached)) => Ok(cached),
+            (StatusCode::TOO_MANY_REQUESTS, cached) => {
+                let until = response
+                    .headers()
+                    .get(header::RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(parse_retry_after)
+                    .and_then(|delay| Utc::now().checked_add_signed(delay))
+                    .unwrap_or_else(|| Utc::now() + DEFAULT_RETRY_AFTER);
+                let entry = Entry {
+                    retry_after: Some(until),
+                    ..entry
+                };
+                self.write(&entry_path, &serde_json::to_vec(&entry)?)
+                    .await?;
+                cached.ok_or(Error::RateLimitedError(until))
+            }
+            _ => {
+                let response = response.error_for_status()?;
+                let header = |name| {
+                    response
+                        .headers()
+                        .get(name)
+                        .and_then(|value: &HeaderValue| value.to_str().ok())
+                        .map(ToOwned::to_owned)
+                };
+                let entry = Entry {
+                    etag: header(header::ETAG),
+                    last_modified: header(header::LAST_MODIFIED),
+                    retry_after: None,
+                };
+                let body = response.bytes().await?.to_vec();
+                self.write(&body_path, &body).await?;
+                self.write(&entry_path, &serde_json::to_vec(&entry)?)
+                    .await?;
+                Ok(body)
+            }
+        }
+    }
+
+    /// Replaces a file in the cache through a temporary file so a crash never leaves it half written
+    async fn write(&self, path: &PathBuf, contents: &[u8]) -> Result<()> {
+        tokio::fs::create_dir_all(&self.directory).await?;
+        let mut temporary = path.clone().into_os_string();
+        temporary.push(".tmp");
+        tokio::fs::write(&temporary, contents).await?;
+        tokio::fs::rename(temporary, path).await?;
+        Ok(())
+    }
+}
+
+/// Reads a file, treating it not existing as nothing being cached yet
+async fn read_optional(path: &PathBuf) -> Result<Option<Vec<u8>>> {
+    match tokio::fs::read(path).await {
+        Ok(contents) => Ok(Some(contents)),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Identifies the cached response by the URL and the headers sent with the request,
+/// since e.g. a different API key or `Accept` can get a different response from the same URL
+fn cache_key(request: &reqwest::Request) -> String {
+    let mut headers: Vec<_> = request.headers().iter().collect();
+    headers.sort_by(|(name, value), (other_name, other_value)| {
+        (name.as_str(), value.as_bytes()).cmp(&(other_name.as_str(), other_value.as_bytes()))
+    });
+    let mut hasher = Sha256::new();
+    hasher.update(request.url().as_str());
+    for (name, value) in headers {
+        hasher.update(b"\n");
+        hasher.update(name.as_str());
+        hasher.update(b": ");
+        hasher.update(value.as_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+fn insert_header(headers: &mut HeaderMap, name: header::HeaderName, value: Option<&str>) {
+    if let Some(value) = value.and_then(|value| HeaderValue::from_str(value).ok()) {
+        headers.insert(name, value);
+    }
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date, anything out of range is ignored
+fn parse_retry_after(value: &str) -> Option<TimeDelta> {
+    match value.trim().parse::<u64>() {
+        Ok(seconds) => TimeDelta::try_seconds(seconds.try_into().ok()?),
+        Err(_) => DateTime::parse_from_rfc2822(value)
+            .ok()
+            .map(|date| (date.to_utc() - Utc::now()).max(TimeDelta::zero())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HttpCache;
+
+    /// Stands in for the agency's server, answering one request per response in order and returning the raw requests
+    async fn serve(
+        responses: Vec<&'static str>,
+    ) -> (reqwest::Url, tokio::task::JoinHandle<Vec<String>>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/incidents", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let read = stream.read(&mut buffer).await.unwrap();
+                    request.extend_from_slice(&buffer[..read]);
+                }
+                stream.write_all(response.as_bytes()).await.unwrap();
+                requests.push(String::from_utf8(request).unwrap().to_lowercase());
+            }
+            requests
+        });
+        (url.parse().unwrap(), handle)
+    }
+
+    #[tokio::test]
+    async fn test_conditional_requests() {
+        let directory = std::env::temp_dir().join("fire-alarm-service-test-conditional-requests");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        let cache = HttpCache::new(&directory);
+        let (url, requests) = serve(vec![
+            "HTTP/1.1 200 OK\r\nETag: \"abc\"\r\nContent-Length: 3\r\nConnection: close\r\n\r\none",
+            "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 3600\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ])
+        .await;
+
+        let client = reqwest::Client::new();
+        let mut bodies = Vec::new();
+        // The last one is not sent since the server asked to wait an hour
+        for _ in 0..4 {
+            bodies.push(cache.send(client.get(url.clone())).await.unwrap());
+        }
+        let requests = requests.await.unwrap();
+        tokio::fs::remove_dir_all(&directory).await.unwrap();
+
+        assert_eq!(bodies, [b"one"; 4]);
+        assert!(!requests[0].contains("if-none-match"));
+        assert!(requests[1].contains("if-none-match: \"abc\"\r\n"));
+        assert!(requests[2].contains("if-none-match: \"abc\"\r\n"));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        use chrono::TimeDelta;
+
+        use super::parse_retry_after;
+
+        assert_eq!(parse_retry_after("120"), Some(TimeDelta::seconds(120)));
+        assert_eq!(parse_retry_after("-5"), None);
+        assert_eq!(parse_retry_after("99999999999999999999"), None);
+        assert_eq!(parse_retry_after(&u64::MAX.to_string()), None);
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"),
+            Some(TimeDelta::zero())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_huge_retry_after() {
+        let directory = std::env::temp_dir().join("fire-alarm-service-test-huge-retry-after");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        let cache = HttpCache::new(&directory);
+        let (url, requests) = serve(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 9223372036854775807\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ])
+        .await;
+
+        // Backs off for the default time rather than overflowing
+        let result = cache.send(reqwest::Client::new().get(url)).await;
+        requests.await.unwrap();
+        tokio::fs::remove_dir_all(&directory).await.unwrap();
+
+        match result {
+            Err(crate::Error::RateLimitedError(until)) => {
+                assert!(until <= chrono::Utc::now() + super::DEFAULT_RETRY_AFTER);
+            }
+            other => panic!("Expected to be rate limited, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_headers_in_key() {
+        let directory = std::env::temp_dir().join("fire-alarm-service-test-headers-in-key");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        let cache = HttpCache::new(&directory);
+        let (url, requests) = serve(vec![
+            "HTTP/1.1 200 OK\r\nETag: \"abc\"\r\nContent-Length: 3\r\nConnection: close\r\n\r\none",
+            "HTTP/1.1 200 OK\r\nETag: \"def\"\r\nContent-Length: 3\r\nConnection: close\r\n\r\ntwo",
+        ])
+        .await;
+
+        // Two configs using different API keys for the same URL
+        let client = reqwest::Client::new();
+        let mut bodies = Vec::new();
+        for key in ["first", "second"] {
+            let request = client.get(url.clone()).header("api_key", key);
+            bodies.push(cache.send(request).await.unwrap());
+        }
+        let requests = requests.await.unwrap();
+        tokio::fs::remove_dir_all(&directory).await.unwrap();
+
+        assert_eq!(bodies, [b"one", b"two"]);
+        assert!(!requests[1].contains("if-none-match"));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_without_cache() {
+        let directory = std::env::temp_dir().join("fire-alarm-service-test-rate-limited");
+        let _ = tokio::fs::remove_dir_all(&directory).await;
+        let cache = HttpCache::new(&directory);
+        let (url, requests) = serve(vec![
+            "HTTP/1.1 429 Too Many Requests\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        ])
+        .await;
+
+        let client = reqwest::Client::new();
+        let first = cache.send(client.get(url.clone())).await;
+        let second = cache.send(client.get(url)).await;
+        requests.await.unwrap();
+        tokio::fs::remove_dir_all(&directory).await.unwrap();
+
+        assert!(matches!(first, Err(crate::Error::RateLimitedError(_))));
+        assert!(matches!(second, Err(crate::Error::RateLimitedError(_))));
+    }
+}
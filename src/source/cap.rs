@@ -0,0 +1,201 @@
+//! Parser for emergency alerts in the [Common Alerting Protocol](https://docs.oasis-open.org/emergency/cap/v1.2/CAP-v1.2.html) version 1.2
+
+use chrono::{DateTime, FixedOffset};
+use serde::Deserialize;
+
+use super::{IncidentSource, Location, cache::HttpCache};
+use crate::{Incident, Result, Severity, Urgency};
+
+/// Reads a CAP `<alert>` document as an incident
+#[derive(Clone, Debug)]
+pub struct Cap {
+    location: Location,
+    cache: Option<HttpCache>,
+}
+
+impl Cap {
+    pub fn new(location: Location) -> Self {
+        Cap {
+            location,
+            cache: None,
+        }
+    }
+
+    /// Keeps the last response from a URL on disk so unchanged feeds are not downloaded again
+    pub fn with_cache(mut self, cache: HttpCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Converts an alert into an incident, exercises, tests, and cancellations are skipped since riders should not be notified about them
+    pub fn parse(buffer: &[u8]) -> Result<Vec<Incident>> {
+        let alert: Alert = quick_xml::de::from_reader(buffer)?;
+        if alert.status != "Actual" || alert.msg_type == "Cancel" {
+            return Ok(Vec::new());
+        }
+        Ok(alert.into_incident().into_iter().collect())
+    }
+}
+
+impl IncidentSource for Cap {
+    async fn fetch(&self) -> Result<Vec<Incident>> {
+        Cap::parse(&self.location.read(self.cache.as_ref()).await?)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Alert {
+    identifier: String,
+    sent: DateTime<FixedOffset>,
+    status: String,   // Actual, Exercise, System, Test, or Draft
+    msg_type: String, // Alert, Update, Cancel, Ack, or Error
+    #[serde(default)]
+    info: Vec<Info>, // One per language
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Info {
+    language: Option<String>, // Defaults to en-US when missing
+    event: String,
+    urgency: String,  // Immediate, Expected, Future, Past, or Unknown
+    severity: String, // Extreme, Severe, Moderate, Minor, or Unknown
+    effective: Option<DateTime<FixedOffset>>,
+    expires: Option<DateTime<FixedOffset>>,
+    headline: Option<String>,
+    description: Option<String>,
+    #[serde(default)]
+    area: Vec<Area>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Area {
+    area_desc: String, // Free-text description of the affected area, e.g. the stations near a fire
+}
+
+impl Alert {
+    fn into_incident(self) -> Option<Incident> {
+        let index = self
+            .info
+            .iter()
+            .position(|info| {
+                info.language
+                    .as_deref()
+                    .is_none_or(|language| language.to_lowercase().starts_with("en"))
+            })
+            .unwrap_or(0);
+        let info = self.info.into_iter().nth(index)?;
+
+        // The area is part of the description so the stations near the alert are matched like any other incident
+        let description = info
+            .headline
+            .into_iter()
+            .chain(info.description)
+            .chain(info.area.into_iter().map(|area| area.area_desc))
+            .map(|text| text.trim().to_string())
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let mut incident = Incident::new(info.effective.unwrap_or(self.sent).to_utc(), description)
+            .with_id(self.identifier)
+            .with_kind(info.event);
+        if let Some(severity) = severity(&info.severity) {
+            incident = incident.with_severity(severity);
+        }
+        if let Some(urgency) = urgency(&info.urgency) {
+            incident = incident.with_urgency(urgency);
+        }
+        if let Some(expires) = info.expires {
+            incident = incident.with_expires(expires.to_utc());
+        }
+        Some(incident)
+    }
+}
+
+fn severity(value: &str) -> Option<Severity> {
+    match value {
+        "Extreme" => Some(Severity::Extreme),
+        "Severe" => Some(Severity::Severe),
+        "Moderate" => Some(Severity::Moderate),
+        "Minor" => Some(Severity::Minor),
+        _ => None, // Unknown
+    }
+}
+
+fn urgency(value: &str) -> Option<Urgency> {
+    match value {
+        "Immediate" => Some(Urgency::Immediate),
+        "Expected" => Some(Urgency::Expected),
+        "Future" => Some(Urgency::Future),
+        "Past" => Some(Urgency::Past),
+        _ => None, // Unknown
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use crate::{Incident, Severity, Urgency};
+
+    const ALERT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <alert xmlns="urn:oasis:names:tc:emergency:cap:1.2">
+            <identifier>DCFEMS-2026-0105-001</identifier>
+            <sender>alerts@fems.dc.gov</sender>
+            <sent>2026-01-05T09:00:00-05:00</sent>
+            <status>Actual</status>
+            <msgType>Alert</msgType>
+            <scope>Public</scope>
+            <info>
+                <language>es-US</language>
+                <category>Fire</category>
+                <event>Incendio estructural</event>
+                <urgency>Immediate</urgency>
+                <severity>Severe</severity>
+                <certainty>Observed</certainty>
+                <headline>Incendio cerca de Dupont Circle</headline>
+            </info>
+            <info>
+                <language>en-US</language>
+                <category>Fire</category>
+                <event>Structure Fire</event>
+                <urgency>Immediate</urgency>
+                <severity>Severe</severity>
+                <certainty>Observed</certainty>
+                <expires>2026-01-05T13:00:00-05:00</expires>
+                <headline>Structure fire near Dupont Circle</headline>
+                <description>Avoid the area, expect smoke.</description>
+                <area>
+                    <areaDesc>Dupont Circle</areaDesc>
+                </area>
+            </info>
+        </alert>"#;
+
+    #[test]
+    fn test_parse_alert() {
+        let incidents = super::Cap::parse(ALERT.as_bytes()).unwrap();
+        assert_eq!(
+            incidents,
+            [Incident::new(
+                Utc.with_ymd_and_hms(2026, 1, 5, 14, 0, 0).unwrap(),
+                String::from(
+                    "Structure fire near Dupont Circle\nAvoid the area, expect smoke.\nDupont Circle"
+                )
+            )
+            .with_id("DCFEMS-2026-0105-001")
+            .with_kind("Structure Fire")
+            .with_severity(Severity::Severe)
+            .with_urgency(Urgency::Immediate)
+            .with_expires(Utc.with_ymd_and_hms(2026, 1, 5, 18, 0, 0).unwrap())]
+        );
+    }
+
+    #[test]
+    fn test_skip_exercise() {
+        let exercise = ALERT.replace("<status>Actual</status>", "<status>Exercise</status>");
+        assert_eq!(super::Cap::parse(exercise.as_bytes()).unwrap(), []);
+    }
+}
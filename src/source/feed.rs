@@ -0,0 +1,159 @@
+//! Parser for service alerts published as an RSS or Atom feed
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+
+use super::{IncidentSource, Location, cache::HttpCache, localize};
+use crate::{Incident, Result};
+
+/// Formats seen in feeds that leave out the timezone, which are assumed to be in the agency's local time
+const LOCAL_FORMATS: [&str; 4] = ["%FT%T", "%F %T", "%a, %d %b %Y %T", "%d %b %Y %T"];
+
+/// Reads the entries of an RSS or Atom feed as incidents
+#[derive(Clone, Debug)]
+pub struct Feed {
+    location: Location,
+    timezone: Tz,
+    cache: Option<HttpCache>,
+}
+
+impl Feed {
+    pub fn new(location: Location) -> Self {
+        Feed {
+            location,
+            timezone: Tz::UTC,
+            cache: None,
+        }
+    }
+
+    /// Keeps the last response from a URL on disk so unchanged feeds are not downloaded again
+    pub fn with_cache(mut self, cache: HttpCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Timezone for the dates in the feed that do not specify one
+    pub fn with_timezone(mut self, timezone: Tz) -> Self {
+        self.timezone = timezone;
+        self
+    }
+
+    /// Converts the entries of a feed into incidents, any timezone-less dates are treated as being in `timezone`
+    pub fn parse(buffer: &[u8], timezone: Tz) -> Result<Vec<Incident>> {
+        let feed = feed_rs::parser::Builder::new()
+            .timestamp_parser(move |text| parse_timestamp(text, timezone))
+            .build()
+            .parse(buffer)?;
+        let fallback = feed.updated.unwrap_or_else(Utc::now);
+        Ok(feed
+            .entries
+            .into_iter()
+            .map(|entry| {
+                let description = [entry.title, entry.summary]
+                    .into_iter()
+                    .flatten()
+                    .map(|text| text.content.trim().to_string())
+                    .filter(|text| !text.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                Incident::new(entry.updated.unwrap_or(fallback), description).with_id(entry.id)
+            })
+            .collect())
+    }
+}
+
+impl IncidentSource for Feed {
+    async fn fetch(&self) -> Result<Vec<Incident>> {
+        Feed::parse(
+            &self.location.read(self.cache.as_ref()).await?,
+            self.timezone,
+        )
+    }
+}
+
+/// Parses the dates that carry their own offset first before falling back to the local formats
+fn parse_timestamp(text: &str, timezone: Tz) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    DateTime::parse_from_rfc3339(text)
+        .or_else(|_| DateTime::parse_from_rfc2822(text))
+        .map(|datetime| datetime.to_utc())
+        .ok()
+        .or_else(|| {
+            LOCAL_FORMATS
+                .iter()
+                .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+                .and_then(|datetime| localize(datetime, timezone).ok())
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use crate::Incident;
+
+    #[test]
+    fn test_parse_rss() {
+        let rss = r#"<?xml version="1.0"?>
+            <rss version="2.0">
+                <channel>
+                    <title>Service Alerts</title>
+                    <item>
+                        <guid>alert-1</guid>
+                        <title>Red Line delays</title>
+                        <description>Trains are single tracking at Dupont Circle.</description>
+                        <pubDate>Mon, 05 Jan 2026 09:00:00 -0500</pubDate>
+                    </item>
+                    <item>
+                        <guid>alert-2</guid>
+                        <title>Elevator outage at Farragut North</title>
+                        <pubDate>Mon, 05 Jan 2026 09:30:00</pubDate>
+                    </item>
+                </channel>
+            </rss>"#;
+        let incidents = super::Feed::parse(rss.as_bytes(), chrono_tz::US::Eastern).unwrap();
+        assert_eq!(
+            incidents,
+            [
+                Incident::new(
+                    Utc.with_ymd_and_hms(2026, 1, 5, 14, 0, 0).unwrap(),
+                    String::from("Red Line delays\nTrains are single tracking at Dupont Circle.")
+                )
+                .with_id("alert-1"),
+                // No timezone so it is treated as Eastern time like WMATA's dates
+                Incident::new(
+                    Utc.with_ymd_and_hms(2026, 1, 5, 14, 30, 0).unwrap(),
+                    String::from("Elevator outage at Farragut North")
+                )
+                .with_id("alert-2"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_atom() {
+        let atom = r#"<?xml version="1.0" encoding="utf-8"?>
+            <feed xmlns="http://www.w3.org/2005/Atom">
+                <title>Service Alerts</title>
+                <id>urn:alerts</id>
+                <updated>2026-01-05T15:00:00Z</updated>
+                <entry>
+                    <id>urn:alerts:1</id>
+                    <title>Shuttle buses replace trains</title>
+                    <summary>Between Farragut North and Dupont Circle.</summary>
+                    <updated>2026-01-05T10:00:00-05:00</updated>
+                </entry>
+            </feed>"#;
+        let incidents = super::Feed::parse(atom.as_bytes(), chrono_tz::UTC).unwrap();
+        assert_eq!(
+            incidents,
+            [Incident::new(
+                Utc.with_ymd_and_hms(2026, 1, 5, 15, 0, 0).unwrap(),
+                String::from(
+                    "Shuttle buses replace trains\nBetween Farragut North and Dupont Circle."
+                )
+            )
+            .with_id("urn:alerts:1")]
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Reader for incidents collected into JSON and NDJSON files, e.g. by several scrapers
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use super::{IncidentSource, ndjson};
+use crate::{Incident, Result};
+
+/// Merges the incidents from several files and directories of files
+#[derive(Clone, Debug)]
+pub struct Files {
+    paths: Vec<Utf8PathBuf>,
+}
+
+impl Files {
+    pub fn new(paths: impl IntoIterator<Item = impl Into<Utf8PathBuf>>) -> Self {
+        Files {
+            paths: paths.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl IncidentSource for Files {
+    async fn fetch(&self) -> Result<Vec<Incident>> {
+        let mut incidents = Vec::new();
+        for path in &self.paths {
+            if tokio::fs::metadata(path).await?.is_dir() {
+                for file in list_directory(path).await? {
+                    incidents.extend(read_file(&file).await?);
+                }
+            } else {
+                incidents.extend(read_file(path).await?);
+            }
+        }
+        Ok(incidents)
+    }
+}
+
+/// Lists the incident files in a directory, sorted so they are always merged in the same order
+async fn list_directory(path: &Utf8Path) -> Result<Vec<Utf8PathBuf>> {
+    let mut entries = tokio::fs::read_dir(path).await?;
+    let mut files = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        let Ok(file) = Utf8PathBuf::from_path_buf(entry.path()) else {
+            continue; // Not valid UTF-8 so it was not written by one of our scrapers
+        };
+        if matches!(file.extension(), Some("json" | "ndjson" | "jsonl"))
+            && entry.file_type().await?.is_file()
+        {
+            files.push(file);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Reads a file as NDJSON if its extension says so and as a JSON array otherwise
+async fn read_file(path: &Utf8Path) -> Result<Vec<Incident>> {
+    let buffer = tokio::fs::read(path).await?;
+    match path.extension() {
+        Some("ndjson" | "jsonl") => ndjson::parse(&buffer),
+        _ => Ok(serde_json::from_slice(&buffer)?),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[tokio::test]
+    async fn test_fetch_files() {
+        use chrono::{TimeZone, Utc};
+        use tokio::fs;
+
+        use crate::{Incident, IncidentSource};
+
+        let directory = std::env::temp_dir().join("fire-alarm-service-test-fetch-files");
+        let _ = fs::remove_dir_all(&directory).await;
+        fs::create_dir_all(directory.join("scrapers"))
+            .await
+            .unwrap();
+        fs::write(
+            directory.join("array.json"),
+            r#"[{"timestamp": "2026-01-05T14:00:00Z", "description": "Hello there"}]"#,
+        )
+        .await
+        .unwrap();
+        fs::write(
+            directory.join("scrapers/b.ndjson"),
+            r#"{"timestamp": "2026-01-05T16:00:00Z", "description": "It's over Anakin"}"#,
+        )
+        .await
+        .unwrap();
+        fs::write(
+            directory.join("scrapers/a.json"),
+            r#"[{"timestamp": "2026-01-05T15:00:00Z", "description": "General Konobi!"}]"#,
+        )
+        .await
+        .unwrap();
+        fs::write(directory.join("scrapers/notes.txt"), "Not incidents")
+            .await
+            .unwrap();
+
+        let directory = camino::Utf8PathBuf::from_path_buf(directory).unwrap();
+        let source = super::Files::new([directory.join("array.json"), directory.join("scrapers")]);
+        let result = source.fetch().await;
+        if let Err(error) = fs::remove_dir_all(&directory).await {
+            eprintln!("Failed to remove {directory}: {error}");
+        }
+
+        let incident = |hour, description: &str| {
+            Incident::new(
+                Utc.with_ymd_and_hms(2026, 1, 5, hour, 0, 0).unwrap(),
+                description.to_string(),
+            )
+        };
+        assert_eq!(
+            result.unwrap(),
+            [
+                incident(14, "Hello there"),
+                incident(15, "General Konobi!"),
+                incident(16, "It's over Anakin"),
+            ]
+        );
+    }
+}
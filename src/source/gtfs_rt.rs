@@ -0,0 +1,316 @@
+//! Decoder for the service alerts in a [GTFS-Realtime](https://gtfs.org/documentation/realtime/reference/) feed
+//!
+//! Only the parts of `gtfs-realtime.proto` that are turned into [`Incident`]s are declared here,
+//! everything else in the feed is skipped over while decoding.
+
+use chrono::{DateTime, Utc};
+use prost::Message;
+
+use super::{IncidentSource, Location, cache::HttpCache};
+use crate::{Incident, Result, Severity};
+
+/// Reads the service alerts from a GTFS-Realtime `FeedMessage`
+#[derive(Clone, Debug)]
+pub struct GtfsRt {
+    location: Location,
+    cache: Option<HttpCache>,
+}
+
+impl GtfsRt {
+    pub fn new(location: Location) -> Self {
+        GtfsRt {
+            location,
+            cache: None,
+        }
+    }
+
+    /// Keeps the last response from a URL on disk so unchanged feeds are not downloaded again
+    pub fn with_cache(mut self, cache: HttpCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Converts the alerts in an encoded `FeedMessage` into incidents
+    pub fn decode(buffer: &[u8]) -> Result<Vec<Incident>> {
+        let feed = FeedMessage::decode(buffer)?;
+        let fallback = feed
+            .header
+            .timestamp
+            .and_then(timestamp)
+            .unwrap_or_else(Utc::now);
+        Ok(feed
+            .entity
+            .into_iter()
+            .filter(|entity| !entity.is_deleted.unwrap_or(false))
+            .filter_map(|entity| Some((entity.id, entity.alert?)))
+            .map(|(id, alert)| alert.into_incident(id, fallback))
+            .collect())
+    }
+}
+
+impl IncidentSource for GtfsRt {
+    async fn fetch(&self) -> Result<Vec<Incident>> {
+        GtfsRt::decode(&self.location.read(self.cache.as_ref()).await?)
+    }
+}
+
+/// Converts a POSIX timestamp from the feed, which are in seconds
+fn timestamp(seconds: u64) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(seconds.try_into().ok()?, 0)
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct FeedMessage {
+    #[prost(message, required, tag = "1")]
+    header: FeedHeader,
+    #[prost(message, repeated, tag = "2")]
+    entity: Vec<FeedEntity>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct FeedHeader {
+    #[prost(string, required, tag = "1")]
+    gtfs_realtime_version: String,
+    #[prost(uint64, optional, tag = "3")]
+    timestamp: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct FeedEntity {
+    #[prost(string, required, tag = "1")]
+    id: String,
+    #[prost(bool, optional, tag = "2")]
+    is_deleted: Option<bool>,
+    #[prost(message, optional, tag = "5")]
+    alert: Option<Alert>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Alert {
+    #[prost(message, repeated, tag = "1")]
+    active_period: Vec<TimeRange>,
+    #[prost(message, repeated, tag = "5")]
+    informed_entity: Vec<EntitySelector>,
+    #[prost(enumeration = "Effect", optional, tag = "7")]
+    effect: Option<i32>,
+    #[prost(message, optional, tag = "10")]
+    header_text: Option<TranslatedString>,
+    #[prost(message, optional, tag = "11")]
+    description_text: Option<TranslatedString>,
+    #[prost(enumeration = "SeverityLevel", optional, tag = "14")]
+    severity_level: Option<i32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TimeRange {
+    #[prost(uint64, optional, tag = "1")]
+    start: Option<u64>,
+    #[prost(uint64, optional, tag = "2")]
+    end: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct EntitySelector {
+    #[prost(string, optional, tag = "1")]
+    agency_id: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    route_id: Option<String>,
+    #[prost(string, optional, tag = "5")]
+    stop_id: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TranslatedString {
+    #[prost(message, repeated, tag = "1")]
+    translation: Vec<Translation>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Translation {
+    #[prost(string, required, tag = "1")]
+    text: String,
+    #[prost(string, optional, tag = "2")]
+    language: Option<String>,
+}
+
+#[allow(clippy::enum_variant_names)] // Mirrors the names in gtfs-realtime.proto
+#[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+#[repr(i32)]
+enum Effect {
+    NoService = 1,
+    ReducedService = 2,
+    SignificantDelays = 3,
+    Detour = 4,
+    AdditionalService = 5,
+    ModifiedService = 6,
+    OtherEffect = 7,
+    UnknownEffect = 8,
+    StopMoved = 9,
+    NoEffect = 10,
+    AccessibilityIssue = 11,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+#[repr(i32)]
+enum SeverityLevel {
+    UnknownSeverity = 1,
+    Info = 2,
+    Warning = 3,
+    Severe = 4,
+}
+
+impl Alert {
+    fn into_incident(self, id: String, fallback: DateTime<Utc>) -> Incident {
+        // The alert is reported from when it first becomes active and expires when its last period ends
+        let start = self
+            .active_period
+            .iter()
+            .filter_map(|period| period.start.and_then(timestamp))
+            .min();
+        let expires = if self.active_period.iter().all(|period| period.end.is_some()) {
+            self.active_period
+                .iter()
+                .filter_map(|period| period.end.and_then(timestamp))
+                .max()
+        } else {
+            None // At least one of the periods is open ended
+        };
+
+        let description = [self.header_text, self.description_text]
+            .into_iter()
+            .flatten()
+            .filter_map(TranslatedString::english)
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let mut lines = Vec::new();
+        let mut stops = Vec::new();
+        let mut agency = None;
+        for entity in self.informed_entity {
+            agency = agency.or(entity.agency_id);
+            if let Some(route) = entity.route_id
+                && !lines.contains(&route)
+            {
+                lines.push(route);
+            }
+            if let Some(stop) = entity.stop_id
+                && !stops.contains(&stop)
+            {
+                stops.push(stop);
+            }
+        }
+
+        let mut incident = Incident::new(start.unwrap_or(fallback), description)
+            .with_id(id)
+            .with_lines(lines)
+            .with_stops(stops);
+        if let Some(expires) = expires {
+            incident = incident.with_expires(expires);
+        }
+        if let Some(agency) = agency {
+            incident = incident.with_agency(agency);
+        }
+        if let Some(effect) = self.effect.and_then(|effect| Effect::try_from(effect).ok()) {
+            incident = incident.with_kind(effect.name());
+        }
+        if let Some(severity) = self
+            .severity_level
+            .and_then(|level| SeverityLevel::try_from(level).ok())
+            .and_then(SeverityLevel::severity)
+        {
+            incident = incident.with_severity(severity);
+        }
+        incident
+    }
+}
+
+impl TranslatedString {
+    /// Picks the English text if there is one, falling back to the untagged or first translation
+    fn english(self) -> Option<String> {
+        let index = self
+            .translation
+            .iter()
+            .position(|translation| {
+                translation
+                    .language
+                    .as_deref()
+                    .is_some_and(|language| language.to_lowercase().starts_with("en"))
+            })
+            .or_else(|| {
+                self.translation
+                    .iter()
+                    .position(|translation| translation.language.is_none())
+            })
+            .unwrap_or(0);
+        self.translation
+            .into_iter()
+            .nth(index)
+            .map(|translation| translation.text)
+    }
+}
+
+impl Effect {
+    fn name(self) -> &'static str {
+        match self {
+            Effect::NoService => "No service",
+            Effect::ReducedService => "Reduced service",
+            Effect::SignificantDelays => "Significant delays",
+            Effect::Detour => "Detour",
+            Effect::AdditionalService => "Additional service",
+            Effect::ModifiedService => "Modified service",
+            Effect::OtherEffect => "Other effect",
+            Effect::UnknownEffect => "Unknown effect",
+            Effect::StopMoved => "Stop moved",
+            Effect::NoEffect => "No effect",
+            Effect::AccessibilityIssue => "Accessibility issue",
+        }
+    }
+}
+
+impl SeverityLevel {
+    fn severity(self) -> Option<Severity> {
+        match self {
+            SeverityLevel::UnknownSeverity => None,
+            SeverityLevel::Info => Some(Severity::Minor),
+            SeverityLevel::Warning => Some(Severity::Moderate),
+            SeverityLevel::Severe => Some(Severity::Severe),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[tokio::test]
+    async fn test_decode_alerts() {
+        use chrono::TimeZone;
+
+        let source = super::GtfsRt::new("tests/fixtures/alerts.pb".parse().unwrap());
+        let incidents = crate::IncidentSource::fetch(&source).await.unwrap();
+
+        let start = chrono::Utc.with_ymd_and_hms(2026, 1, 5, 14, 0, 0).unwrap();
+        let end = chrono::Utc.with_ymd_and_hms(2026, 1, 5, 22, 0, 0).unwrap();
+        let header = chrono::Utc.with_ymd_and_hms(2026, 1, 5, 15, 0, 0).unwrap();
+        assert_eq!(
+            incidents,
+            [
+                crate::Incident::new(
+                    start,
+                    String::from(
+                        "Red Line single tracking\nTrains single track between Farragut North and Dupont Circle."
+                    )
+                )
+                .with_id("alert-1")
+                .with_kind("Significant delays")
+                .with_severity(crate::Severity::Moderate)
+                .with_lines(["RED"])
+                .with_stops(["A02", "A03"])
+                .with_expires(end)
+                .with_agency("WMATA"),
+                crate::Incident::new(header, String::from("Elevator out of service"))
+                    .with_id("alert-2")
+                    .with_kind("Accessibility issue")
+                    .with_stops(["A01"]),
+            ]
+        );
+    }
+}
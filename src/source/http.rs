@@ -0,0 +1,211 @@
+//! Client for any HTTP API that returns incidents as JSON, described by a config file instead of a struct per agency
+
+use std::collections::HashMap;
+
+use camino::Utf8Path;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{IncidentSource, cache::HttpCache, localize};
+use crate::{Error, Incident, Result};
+
+/// Format of timestamps that do not include an offset when none is configured, e.g. WMATA's `2010-07-29T14:21:28`
+const DEFAULT_FORMAT: &str = "%FT%T";
+
+/// Where to request the incidents from and where each [`Incident`] field is found in the response.
+/// Locations within the JSON are [JSON pointers](https://datatracker.ietf.org/doc/html/rfc6901), e.g. `/Incidents`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct HttpJson {
+    url: Url,
+    #[serde(default)]
+    headers: HashMap<String, String>, // Sent with every request, e.g. WMATA's `api_key`
+    #[serde(default)]
+    incidents: String, // Location of the array of incidents in the response, empty if the response is the array
+    timestamp: String,
+    timestamp_format: Option<String>, // Only needed for timestamps that are not RFC 3339
+    #[serde(default = "default_timezone")]
+    timezone: chrono_tz::Tz, // Used for timestamps that do not include an offset
+    description: String,
+    id: Option<String>,
+    #[serde(skip)]
+    client: reqwest::Client,
+    #[serde(skip)]
+    cache: Option<HttpCache>,
+}
+
+fn default_timezone() -> chrono_tz::Tz {
+    chrono_tz::UTC
+}
+
+impl HttpJson {
+    /// Reads the description of the API from a JSON config file
+    pub async fn load(path: impl AsRef<Utf8Path>) -> Result<Self> {
+        Ok(serde_json::from_slice(
+            &tokio::fs::read(path.as_ref()).await?,
+        )?)
+    }
+
+    /// Uses a different URL than the config, e.g. for a mock server
+    pub fn with_url(mut self, url: Url) -> Self {
+        self.url = url;
+        self
+    }
+
+    /// Keeps the last response on disk so unchanged incidents are not downloaded again, and backs off when rate limited
+    pub fn with_cache(mut self, cache: HttpCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Maps each of the incidents in a response body to an [`Incident`]
+    pub fn convert(&self, body: &Value) -> Result<Vec<Incident>> {
+        match body.pointer(&self.incidents) {
+            Some(Value::Array(incidents)) => incidents
+                .iter()
+                .map(|incident| self.convert_incident(incident))
+                .collect(),
+            _ => Err(Error::MissingFieldError(self.incidents.clone())),
+        }
+    }
+
+    fn convert_incident(&self, incident: &Value) -> Result<Incident> {
+        let timestamp = self.parse_timestamp(string_at(incident, &self.timestamp)?)?;
+        let description = string_at(incident, &self.description)?;
+        let mut converted = Incident::new(timestamp, description.to_owned());
+        let id = self.id.as_deref().and_then(|id| incident.pointer(id));
+        match id {
+            None | Some(Value::Null) => {}
+            Some(Value::String(id)) => converted = converted.with_id(id.clone()),
+            Some(id) => converted = converted.with_id(id.to_string()), // e.g. numeric IDs
+        }
+        Ok(converted)
+    }
+
+    fn parse_timestamp(&self, timestamp: &str) -> Result<DateTime<Utc>> {
+        if self.timestamp_format.is_none()
+            && let Ok(timestamp) = DateTime::parse_from_rfc3339(timestamp)
+        {
+            return Ok(timestamp.to_utc());
+        }
+        let format = self.timestamp_format.as_deref().unwrap_or(DEFAULT_FORMAT);
+        localize(
+            NaiveDateTime::parse_from_str(timestamp, format)?,
+            self.timezone,
+        )
+    }
+}
+
+/// Finds a required string in an incident
+fn string_at<'a>(incident: &'a Value, pointer: &str) -> Result<&'a str> {
+    incident
+        .pointer(pointer)
+        .and_then(Value::as_str)
+        .ok_or_else(|| Error::MissingFieldError(pointer.to_owned()))
+}
+
+impl IncidentSource for HttpJson {
+    async fn fetch(&self) -> Result<Vec<Incident>> {
+        let mut request = self.client.get(self.url.clone());
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        let body: Value = match &self.cache {
+            Some(cache) => serde_json::from_slice(&cache.send(request).await?)?,
+            None => request.send().await?.error_for_status()?.json().await?,
+        };
+        self.convert(&body)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use super::HttpJson;
+    use crate::{Incident, IncidentSource};
+
+    const CONFIG: &str = r#"{
+        "url": "https://api.wmata.com/Incidents.svc/json/Incidents",
+        "headers": { "api_key": "secret" },
+        "incidents": "/Incidents",
+        "timestamp": "/DateUpdated",
+        "timezone": "US/Eastern",
+        "description": "/Description",
+        "id": "/IncidentID"
+    }"#;
+
+    /// Stands in for the agency's server, answering a single request with the body and returning the raw request
+    async fn serve_once(body: &'static str) -> (reqwest::Url, tokio::task::JoinHandle<String>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/incidents", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..read]);
+            }
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (url.parse().unwrap(), handle)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_mapped_fields() {
+        let (url, request) = serve_once(
+            r#"{
+                "Incidents": [
+                    {
+                        "DateUpdated": "2010-07-29T14:21:28",
+                        "Description": "Red Line: Expect residual delays",
+                        "IncidentID": "3754F8B2"
+                    },
+                    {
+                        "DateUpdated": "2010-07-29T18:21:28Z",
+                        "Description": "Blue/Orange Line: Single tracking",
+                        "IncidentID": null
+                    }
+                ]
+            }"#,
+        )
+        .await;
+        let source = serde_json::from_str::<HttpJson>(CONFIG)
+            .unwrap()
+            .with_url(url);
+        let incidents = source.fetch().await.unwrap();
+        let request = request.await.unwrap();
+
+        let timestamp = Utc.with_ymd_and_hms(2010, 7, 29, 18, 21, 28).unwrap(); // EDT is UTC-4
+        assert!(request.to_lowercase().contains("api_key: secret\r\n"));
+        assert_eq!(
+            incidents,
+            [
+                Incident::new(timestamp, String::from("Red Line: Expect residual delays"))
+                    .with_id("3754F8B2"),
+                Incident::new(timestamp, String::from("Blue/Orange Line: Single tracking")),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_field() {
+        let source: HttpJson = serde_json::from_str(CONFIG).unwrap();
+        let body = serde_json::json!({
+            "Incidents": [{ "DateUpdated": "2010-07-29T14:21:28" }]
+        });
+        assert!(matches!(
+            source.convert(&body),
+            Err(crate::Error::MissingFieldError(field)) if field == "/Description"
+        ));
+    }
+}
@@ -0,0 +1,148 @@
+//! Places that [`Incident`]s can be gathered from
+
+use std::{fmt, str::FromStr};
+
+use camino::Utf8PathBuf;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use reqwest::Url;
+
+use crate::{Error, Incident, Result};
+use cache::HttpCache;
+
+pub mod cache;
+#[cfg(feature = "cap")]
+pub mod cap;
+#[cfg(feature = "feed")]
+pub mod feed;
+pub mod files;
+#[cfg(feature = "gtfs-rt")]
+pub mod gtfs_rt;
+pub mod http;
+pub mod ndjson;
+#[cfg(feature = "wmata")]
+pub mod wmata;
+
+/// Anything that can report the incidents that are currently affecting a transit system
+pub trait IncidentSource {
+    /// Gathers the current list of incidents from the source
+    fn fetch(&self) -> impl Future<Output = Result<Vec<Incident>>> + Send;
+
+    /// Whether [`IncidentSource::fetch`] reports every incident that is currently active,
+    /// so any that are missing can be treated as resolved
+    fn complete(&self) -> bool {
+        true
+    }
+
+    /// ID of the agency whose incidents this source reports, if it only reports one agency's,
+    /// so that only that agency's incidents are treated as resolved when they go missing
+    fn agency(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Incidents that were already gathered up front, e.g. read from stdin
+impl IncidentSource for Vec<Incident> {
+    async fn fetch(&self) -> Result<Vec<Incident>> {
+        Ok(self.clone())
+    }
+}
+
+/// Only some of the incidents that are currently active, e.g. a batch that was streamed or pushed to the service
+#[derive(Clone, Debug)]
+pub struct Partial<S>(pub S);
+
+impl<S: IncidentSource + Sync> IncidentSource for Partial<S> {
+    async fn fetch(&self) -> Result<Vec<Incident>> {
+        self.0.fetch().await
+    }
+
+    fn complete(&self) -> bool {
+        false
+    }
+
+    fn agency(&self) -> Option<&str> {
+        self.0.agency()
+    }
+}
+
+/// Incidents reported by one agency, which are only matched against the stations and routes it owns.
+/// Incidents that already name an agency are left alone, and with no agency this passes everything through as is.
+#[derive(Clone, Debug)]
+pub struct Scoped<S> {
+    pub agency: Option<String>,
+    pub source: S,
+}
+
+impl<S: IncidentSource + Sync> IncidentSource for Scoped<S> {
+    async fn fetch(&self) -> Result<Vec<Incident>> {
+        let mut incidents = self.source.fetch().await?;
+        if let Some(agency) = &self.agency {
+            for incident in &mut incidents {
+                incident.agency.get_or_insert_with(|| agency.clone());
+            }
+        }
+        Ok(incidents)
+    }
+
+    fn complete(&self) -> bool {
+        self.source.complete()
+    }
+
+    fn agency(&self) -> Option<&str> {
+        self.agency.as_deref().or_else(|| self.source.agency())
+    }
+}
+
+/// Converts a timezone-less datetime reported by an agency into UTC, e.g. WMATA reports everything in US Eastern time
+pub fn localize(datetime: NaiveDateTime, timezone: chrono_tz::Tz) -> Result<DateTime<Utc>> {
+    timezone
+        .from_local_datetime(&datetime)
+        .single()
+        .map(|datetime| datetime.to_utc())
+        .ok_or(Error::LocalTimeError(datetime, timezone))
+}
+
+/// Where a feed published by an agency can be read from
+#[derive(Clone, Debug, PartialEq)]
+pub enum Location {
+    Path(Utf8PathBuf),
+    Url(Url),
+}
+
+impl Location {
+    /// Reads the whole feed into memory, going through the cache for URLs if there is one
+    pub async fn read(&self, cache: Option<&HttpCache>) -> Result<Vec<u8>> {
+        match (self, cache) {
+            (Location::Path(path), _) => Ok(tokio::fs::read(path).await?),
+            (Location::Url(url), Some(cache)) => {
+                cache.send(reqwest::Client::new().get(url.clone())).await
+            }
+            (Location::Url(url), None) => Ok(reqwest::get(url.clone())
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await?
+                .into()),
+        }
+    }
+}
+
+/// Anything starting with `http://` or `https://` is treated as a URL and everything else as a file path
+impl FromStr for Location {
+    type Err = std::convert::Infallible;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match Url::parse(s) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(Location::Url(url)),
+            _ => Ok(Location::Path(Utf8PathBuf::from(s))),
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Path(path) => path.fmt(f),
+            Location::Url(url) => url.fmt(f),
+        }
+    }
+}
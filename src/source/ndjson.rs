@@ -0,0 +1,204 @@
+//! Reader for incidents written as one JSON object per line
+
+use std::time::Duration;
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, Lines};
+
+use crate::{Incident, Result};
+
+/// Longest a batch is held back for when the producer never goes quiet
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(10);
+
+/// Most incidents sent in one batch when the producer never goes quiet
+pub const DEFAULT_MAX_SIZE: usize = 1000;
+
+/// Streams incidents from newline-delimited JSON, e.g. piped in from a long-running producer
+pub struct Ndjson<R> {
+    lines: Lines<R>,
+    line_number: usize,
+    max_age: Duration,
+    max_size: usize,
+}
+
+impl<R: AsyncBufRead + Unpin> Ndjson<R> {
+    pub fn new(reader: R) -> Self {
+        Ndjson {
+            lines: reader.lines(),
+            line_number: 0,
+            max_age: DEFAULT_MAX_AGE,
+            max_size: DEFAULT_MAX_SIZE,
+        }
+    }
+
+    /// Sends a batch once its first incident has been waiting this long, even if the producer is still writing
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Sends a batch once it has this many incidents, even if the producer is still writing
+    pub fn with_max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Reads incidents until the producer has been quiet for `idle` or closes the stream, or the batch reaches its maximum age or size.
+    /// Returns `None` once the stream is finished.
+    pub async fn next_batch(&mut self, idle: Duration) -> Result<Option<Vec<Incident>>> {
+        let mut batch = Vec::new();
+        let mut started = tokio::time::Instant::now();
+        loop {
+            let line = if batch.is_empty() {
+                let line = self.lines.next_line().await?; // Nothing to send yet so wait for as long as it takes
+                started = tokio::time::Instant::now();
+                line
+            } else {
+                if batch.len() >= self.max_size {
+                    return Ok(Some(batch));
+                }
+                let remaining = self.max_age.saturating_sub(started.elapsed());
+                // `next_line` is cancel safe so nothing is lost if the timeout fires mid-line
+                match tokio::time::timeout(idle.min(remaining), self.lines.next_line()).await {
+                    Ok(line) => line?,
+                    Err(_) => return Ok(Some(batch)),
+                }
+            };
+            match line {
+                Some(line) => {
+                    self.line_number += 1;
+                    batch.extend(parse_line(&line, self.line_number));
+                }
+                None if batch.is_empty() => return Ok(None),
+                None => return Ok(Some(batch)),
+            }
+        }
+    }
+}
+
+/// Parses a whole buffer of newline-delimited JSON at once, skipping and reporting any malformed lines
+pub fn parse(buffer: &[u8]) -> Result<Vec<Incident>> {
+    Ok(std::str::from_utf8(buffer)
+        .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))?
+        .lines()
+        .enumerate()
+        .filter_map(|(index, line)| parse_line(line, index + 1))
+        .collect())
+}
+
+fn parse_line(line: &str, line_number: usize) -> Option<Incident> {
+    if line.trim().is_empty() {
+        return None;
+    }
+    match serde_json::from_str(line) {
+        Ok(incident) => Some(incident),
+        Err(error) => {
+            // One bad record should not stop the rest from being delivered
+            #[cfg(feature = "log")]
+            log::warn!("Skipping malformed incident on line {line_number}: {error}");
+            #[cfg(not(feature = "log"))]
+            eprintln!("Skipping malformed incident on line {line_number}: {error}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use chrono::{TimeZone, Utc};
+    use tokio::io::AsyncWriteExt;
+
+    use super::Ndjson;
+    use crate::Incident;
+
+    #[test]
+    fn test_skip_malformed_lines() {
+        let input = concat!(
+            r#"{"timestamp": "2026-01-05T14:00:00Z", "description": "Hello there"}"#,
+            "\n\n",
+            r#"{"timestamp": "not a date", "description": "Broken"}"#,
+            "\n",
+            r#"{"timestamp": "2026-01-05T15:00:00Z", "description": "General Konobi!"}"#,
+        );
+        assert_eq!(
+            super::parse(input.as_bytes()).unwrap(),
+            [
+                Incident::new(
+                    Utc.with_ymd_and_hms(2026, 1, 5, 14, 0, 0).unwrap(),
+                    String::from("Hello there")
+                ),
+                Incident::new(
+                    Utc.with_ymd_and_hms(2026, 1, 5, 15, 0, 0).unwrap(),
+                    String::from("General Konobi!")
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_when_idle() {
+        let (reader, mut writer) = tokio::io::duplex(1024);
+        let mut stream = Ndjson::new(tokio::io::BufReader::new(reader));
+        let idle = Duration::from_millis(50);
+
+        writer
+            .write_all(b"{\"timestamp\": \"2026-01-05T14:00:00Z\", \"description\": \"First\"}\n")
+            .await
+            .unwrap();
+        writer.write_all(b"oops\n").await.unwrap();
+
+        // The producer is still running but has gone quiet so the first batch is sent
+        let batch = stream.next_batch(idle).await.unwrap().unwrap();
+        assert_eq!(batch.len(), 1);
+
+        writer
+            .write_all(b"{\"timestamp\": \"2026-01-05T15:00:00Z\", \"description\": \"Second\"}\n")
+            .await
+            .unwrap();
+        drop(writer);
+
+        let batch = stream.next_batch(idle).await.unwrap().unwrap();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(stream.next_batch(idle).await.unwrap(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_batch_limits() {
+        const LINE: &[u8] =
+            b"{\"timestamp\": \"2026-01-05T14:00:00Z\", \"description\": \"Delays\"}\n";
+
+        let (reader, mut writer) = tokio::io::duplex(1024);
+        let mut stream = Ndjson::new(tokio::io::BufReader::new(reader))
+            .with_max_age(Duration::from_millis(150))
+            .with_max_size(3);
+        let idle = Duration::from_secs(60);
+        let batch = tokio::spawn(async move {
+            let batch = stream.next_batch(idle).await.unwrap().unwrap();
+            (stream, batch)
+        });
+
+        // A chatty producer that never pauses for long enough to count as idle
+        for _ in 0..2 {
+            writer.write_all(LINE).await.unwrap();
+            tokio::task::yield_now().await;
+            tokio::time::advance(Duration::from_millis(100)).await;
+        }
+        tokio::task::yield_now().await;
+        writer.write_all(LINE).await.unwrap();
+        let (mut stream, batch) = batch.await.unwrap();
+        assert_eq!(batch.len(), 2);
+
+        // Lines already waiting are cut off at the maximum size
+        for _ in 0..4 {
+            writer.write_all(LINE).await.unwrap();
+        }
+        drop(writer);
+        let sizes = [
+            stream.next_batch(idle).await.unwrap().unwrap().len(),
+            stream.next_batch(idle).await.unwrap().unwrap().len(),
+        ];
+        assert_eq!(sizes, [3, 2]);
+        assert_eq!(stream.next_batch(idle).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,399 @@
+//! Client for the [WMATA](https://developer.wmata.com/) rail incidents API
+
+use reqwest::{Url, header::HeaderValue};
+use serde::{Deserialize, de::DeserializeOwned};
+
+use super::{IncidentSource, cache::HttpCache, localize};
+use crate::{Incident, Outage, Result};
+
+/// Default endpoint for getting the rail incidents
+pub const INCIDENTS_ENDPOINT: &str = "https://api.wmata.com/Incidents.svc/json/Incidents";
+
+/// Default endpoint for getting the bus incidents
+pub const BUS_INCIDENTS_ENDPOINT: &str = "https://api.wmata.com/Incidents.svc/json/BusIncidents";
+
+/// Default endpoint for getting the elevator and escalator outages
+pub const ELEVATOR_INCIDENTS_ENDPOINT: &str =
+    "https://api.wmata.com/Incidents.svc/json/ElevatorIncidents";
+
+/// Fetches the rail incidents currently reported by WMATA
+#[derive(Clone, Debug)]
+pub struct Wmata {
+    client: reqwest::Client,
+    endpoint: Url,
+    key: HeaderValue,
+    cache: Option<HttpCache>,
+    outages: Option<Url>,
+    buses: Option<Url>,
+}
+
+impl Wmata {
+    pub fn new(key: HeaderValue) -> Self {
+        Wmata {
+            client: reqwest::Client::new(),
+            endpoint: Url::parse(INCIDENTS_ENDPOINT).unwrap(),
+            key,
+            cache: None,
+            outages: None,
+            buses: None,
+        }
+    }
+
+    /// Uses a different endpoint than [`INCIDENTS_ENDPOINT`], e.g. for a mock server
+    pub fn with_endpoint(mut self, endpoint: Url) -> Self {
+        self.endpoint = endpoint;
+        self
+    }
+
+    /// Keeps the last response on disk so unchanged incidents are not downloaded again, and backs off when rate limited
+    pub fn with_cache(mut self, cache: HttpCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Also fetches the elevator and escalator outages, e.g. from [`ELEVATOR_INCIDENTS_ENDPOINT`]
+    pub fn with_outages(mut self, endpoint: Url) -> Self {
+        self.outages = Some(endpoint);
+        self
+    }
+
+    /// Also fetches the bus incidents, e.g. from [`BUS_INCIDENTS_ENDPOINT`]
+    pub fn with_buses(mut self, endpoint: Url) -> Self {
+        self.buses = Some(endpoint);
+        self
+    }
+
+    async fn get<T: DeserializeOwned>(&self, endpoint: &Url) -> Result<T> {
+        let request = self
+            .client
+            .get(endpoint.clone())
+            .header("api_key", self.key.clone());
+        Ok(match &self.cache {
+            Some(cache) => serde_json::from_slice(&cache.send(request).await?)?,
+            None => request.send().await?.error_for_status()?.json().await?,
+        })
+    }
+}
+
+impl IncidentSource for Wmata {
+    async fn fetch(&self) -> Result<Vec<Incident>> {
+        let mut incidents: Vec<Incident> = self
+            .get::<IncidentsWmata>(&self.endpoint)
+            .await?
+            .try_into()?;
+        if let Some(endpoint) = &self.outages {
+            let outages: Vec<Incident> = self
+                .get::<ElevatorIncidentsWmata>(endpoint)
+                .await?
+                .try_into()?;
+            incidents.extend(outages);
+        }
+        if let Some(endpoint) = &self.buses {
+            let buses: Vec<Incident> = self.get::<BusIncidentsWmata>(endpoint).await?.try_into()?;
+            incidents.extend(buses);
+        }
+        Ok(incidents)
+    }
+}
+
+#[allow(non_snake_case)] // Necessary since the JSON key is in Pascal case
+#[derive(Deserialize)]
+struct IncidentsWmata {
+    Incidents: Vec<IncidentWmata>, // Array containing rail disruption information
+}
+
+#[allow(non_snake_case)] // Necessary since the JSON key is in Pascal case
+#[derive(Deserialize)]
+struct IncidentWmata {
+    DateUpdated: String, // Date and time (Eastern Standard Time) of last update. Will be in YYYY-MM-DDTHH:mm:SS format (e.g.: 2010-07-29T14:21:28).
+    // DelaySeverity: String, // Deprecated
+    Description: String, // Free-text description of the incident.
+    // EmergencyText: String, // Deprecated
+    // EndLocationFullName: String, // Deprecated
+    // PassengerDelay: String, // Deprecated
+    // StartLocationFullName: String, // Deprecated
+    IncidentID: Option<String>,    // Unique identifier for an incident.
+    IncidentType: Option<String>, // Free-text description of the incident type. Usually Delay or Alert but is subject to change at any time.
+    LinesAffected: Option<String>, // Semi-colon and space separated list of line codes (e.g.: RD; or BL; OR; or BL; OR; RD;).
+}
+
+#[allow(non_snake_case)] // Necessary since the JSON key is in Pascal case
+#[derive(Deserialize)]
+struct BusIncidentsWmata {
+    BusIncidents: Vec<BusIncidentWmata>, // Array containing bus incident information
+}
+
+#[allow(non_snake_case)] // Necessary since the JSON key is in Pascal case
+#[derive(Deserialize)]
+struct BusIncidentWmata {
+    DateUpdated: String, // Date and time (Eastern Standard Time) of last update. Will be in YYYY-MM-DDTHH:mm:SS format (e.g.: 2014-10-28T08:13:03).
+    Description: String, // Free-text description of the delay or incident.
+    IncidentID: Option<String>, // Unique identifier for an incident.
+    IncidentType: Option<String>, // Free-text description of the incident type. Usually Delay or Alert but is subject to change at any time.
+    #[serde(default)]
+    RoutesAffected: Vec<String>, // Array containing routes affected. Routes listed are usually identical to base route names (i.e.: not 10Av1 or 10Av2, but 10A), but may differ from what our bus position and route APIs return.
+}
+
+impl TryFrom<BusIncidentsWmata> for Vec<Incident> {
+    type Error = crate::Error;
+    fn try_from(value: BusIncidentsWmata) -> Result<Self> {
+        value
+            .BusIncidents
+            .into_iter()
+            .map(|incident| incident.try_into())
+            .collect()
+    }
+}
+
+impl TryFrom<BusIncidentWmata> for Incident {
+    type Error = crate::Error;
+    fn try_from(value: BusIncidentWmata) -> Result<Self> {
+        let eastern_datetime = chrono::NaiveDateTime::parse_from_str(&value.DateUpdated, "%FT%T")?;
+        let mut incident = Incident::new(
+            localize(eastern_datetime, chrono_tz::US::Eastern)?,
+            value.Description,
+        )
+        .with_routes(value.RoutesAffected);
+        if let Some(id) = value.IncidentID {
+            incident = incident.with_id(id);
+        }
+        if let Some(kind) = value.IncidentType {
+            incident = incident.with_kind(kind);
+        }
+        Ok(incident)
+    }
+}
+
+#[allow(non_snake_case)] // Necessary since the JSON key is in Pascal case
+#[derive(Deserialize)]
+struct ElevatorIncidentsWmata {
+    ElevatorIncidents: Vec<ElevatorIncidentWmata>, // Array containing elevator/escalator outage information
+}
+
+#[allow(non_snake_case)] // Necessary since the JSON key is in Pascal case
+#[derive(Deserialize)]
+struct ElevatorIncidentWmata {
+    DateUpdated: String, // Date and time (Eastern Standard Time) outage details were last updated. Will be in YYYY-MM-DDTHH:mm:SS format (e.g.: 2014-10-28T15:59:00).
+    // DisplayOrder: f64, // Deprecated
+    // EstimatedReturnToService: Option<String>, // Estimated date and time (Eastern Standard Time) by when unit is expected to return to normal service. May be NULL.
+    LocationDescription: String, // Free-text description of the unit location within a station (e.g.: Escalator between mezzanine and platform).
+    StationCode: String, // Unit's station code. Use this value in other rail-related APIs to retrieve data about a station.
+    StationName: String, // Full station name, may include entrance information (e.g.: Metro Center, G and 11th St Entrance).
+    // SymptomCode: Option<String>, // Deprecated
+    SymptomDescription: Option<String>, // Description for why the unit is out of service or otherwise in reduced operation.
+    // TimeOutOfService: String, // Deprecated
+    UnitName: String, // Unique identifier for unit, by type (a single elevator and escalator may have the same UnitName, but no two elevators or two escalators will have the same UnitName).
+    UnitType: String, // Type of unit. Will be ELEVATOR or ESCALATOR.
+}
+
+impl TryFrom<ElevatorIncidentsWmata> for Vec<Incident> {
+    type Error = crate::Error;
+    fn try_from(value: ElevatorIncidentsWmata) -> Result<Self> {
+        value
+            .ElevatorIncidents
+            .into_iter()
+            .map(|incident| incident.try_into())
+            .collect()
+    }
+}
+
+impl TryFrom<ElevatorIncidentWmata> for Incident {
+    type Error = crate::Error;
+    fn try_from(value: ElevatorIncidentWmata) -> Result<Self> {
+        let eastern_datetime = chrono::NaiveDateTime::parse_from_str(&value.DateUpdated, "%FT%T")?;
+        let (kind, outage) = match value.UnitType.as_str() {
+            "ELEVATOR" => ("Elevator", Some(Outage::Elevator)),
+            "ESCALATOR" => ("Escalator", Some(Outage::Escalator)),
+            _ => ("Unit", None), // Still reported to everyone subscribed to the station rather than being lost
+        };
+        let mut description = format!(
+            "{kind} out of service at {}: {}",
+            value.StationName, value.LocationDescription
+        );
+        if let Some(symptom) = value.SymptomDescription {
+            description = format!("{description} ({symptom})");
+        }
+        let mut incident = Incident::new(
+            localize(eastern_datetime, chrono_tz::US::Eastern)?,
+            description,
+        )
+        // An elevator and an escalator can share a name
+        .with_id(format!("{} {}", value.UnitType, value.UnitName))
+        .with_kind(kind)
+        .with_stops([value.StationCode]);
+        if let Some(outage) = outage {
+            incident = incident.with_outage(outage);
+        }
+        Ok(incident)
+    }
+}
+
+impl TryFrom<IncidentsWmata> for Vec<Incident> {
+    type Error = crate::Error;
+    fn try_from(value: IncidentsWmata) -> Result<Self> {
+        value
+            .Incidents
+            .into_iter()
+            .map(|incident| incident.try_into())
+            .collect()
+    }
+}
+
+impl TryFrom<IncidentWmata> for Incident {
+    type Error = crate::Error;
+    fn try_from(value: IncidentWmata) -> Result<Self> {
+        let eastern_datetime = chrono::NaiveDateTime::parse_from_str(&value.DateUpdated, "%FT%T")?;
+        let mut incident = Incident::new(
+            localize(eastern_datetime, chrono_tz::US::Eastern)?,
+            value.Description,
+        );
+        if let Some(id) = value.IncidentID {
+            incident = incident.with_id(id);
+        }
+        if let Some(kind) = value.IncidentType {
+            incident = incident.with_kind(kind);
+        }
+        if let Some(lines) = value.LinesAffected {
+            incident = incident.with_lines(
+                lines
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty()),
+            );
+        }
+        Ok(incident)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    fn test_convert_incidents() {
+        use chrono::TimeZone;
+
+        let json = r#"{
+            "Incidents": [
+                {
+                    "DateUpdated": "2010-07-29T14:21:28",
+                    "DelaySeverity": null,
+                    "Description": "Red Line: Expect residual delays to Glenmont due to an earlier signal problem outside Forest Glen.",
+                    "EmergencyText": null,
+                    "EndLocationFullName": null,
+                    "IncidentID": "3754F8B2-A0A6-494E-A4B5-82C9E72DFA74",
+                    "IncidentType": "Delay",
+                    "LinesAffected": "RD;",
+                    "PassengerDelay": 0,
+                    "StartLocationFullName": null
+                },
+                {
+                    "DateUpdated": "2010-07-29T14:21:28",
+                    "Description": "Blue/Orange Line: Single tracking",
+                    "IncidentID": null,
+                    "IncidentType": null,
+                    "LinesAffected": "BL; OR;"
+                }
+            ]
+        }"#;
+        let incidents: super::IncidentsWmata = serde_json::from_str(json).unwrap();
+        let incidents: Vec<crate::Incident> = incidents.try_into().unwrap();
+
+        let timestamp = chrono::Utc
+            .with_ymd_and_hms(2010, 7, 29, 18, 21, 28)
+            .unwrap(); // EDT is UTC-4
+        assert_eq!(
+            incidents,
+            [
+                crate::Incident::new(
+                    timestamp,
+                    String::from(
+                        "Red Line: Expect residual delays to Glenmont due to an earlier signal problem outside Forest Glen."
+                    )
+                )
+                .with_id("3754F8B2-A0A6-494E-A4B5-82C9E72DFA74")
+                .with_kind("Delay")
+                .with_lines(["RD"]),
+                crate::Incident::new(timestamp, String::from("Blue/Orange Line: Single tracking"))
+                    .with_lines(["BL", "OR"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_convert_outages() {
+        use chrono::TimeZone;
+
+        let json = r#"{
+            "ElevatorIncidents": [
+                {
+                    "UnitName": "A03N04",
+                    "UnitType": "ESCALATOR",
+                    "UnitStatus": null,
+                    "StationCode": "A03",
+                    "StationName": "Dupont Circle, Q Street Entrance",
+                    "LocationDescription": "Escalator between mezzanine and platform",
+                    "SymptomCode": null,
+                    "TimeOutOfService": "0950",
+                    "SymptomDescription": "Modernization",
+                    "DisplayOrder": 0,
+                    "DateOutOfServ": "2014-10-27T09:50:00",
+                    "DateUpdated": "2014-10-27T09:50:00",
+                    "EstimatedReturnToService": "2014-11-01T23:59:59"
+                }
+            ]
+        }"#;
+        let incidents: super::ElevatorIncidentsWmata = serde_json::from_str(json).unwrap();
+        let incidents: Vec<crate::Incident> = incidents.try_into().unwrap();
+
+        let timestamp = chrono::Utc
+            .with_ymd_and_hms(2014, 10, 27, 13, 50, 0)
+            .unwrap(); // EDT is UTC-4
+        assert_eq!(
+            incidents,
+            [crate::Incident::new(
+                timestamp,
+                String::from(
+                    "Escalator out of service at Dupont Circle, Q Street Entrance: Escalator between mezzanine and platform (Modernization)"
+                )
+            )
+            .with_id("ESCALATOR A03N04")
+            .with_kind("Escalator")
+            .with_stops(["A03"])
+            .with_outage(crate::Outage::Escalator)]
+        );
+    }
+
+    #[test]
+    fn test_convert_bus_incidents() {
+        use chrono::TimeZone;
+
+        let json = r#"{
+            "BusIncidents": [
+                {
+                    "DateUpdated": "2014-10-28T08:13:03",
+                    "Description": "90, 92, X1, X2, X9: Due to traffic congestion at 8th & H St NE, buses may experience delays.",
+                    "IncidentID": "32297013-57B6-467F-BC6B-93DFA4115652",
+                    "IncidentType": "Delay",
+                    "RoutesAffected": ["90", "92", "X1", "X2", "X9"]
+                }
+            ]
+        }"#;
+        let incidents: super::BusIncidentsWmata = serde_json::from_str(json).unwrap();
+        let incidents: Vec<crate::Incident> = incidents.try_into().unwrap();
+
+        let timestamp = chrono::Utc
+            .with_ymd_and_hms(2014, 10, 28, 12, 13, 3)
+            .unwrap(); // EDT is UTC-4
+        assert_eq!(
+            incidents,
+            [crate::Incident::new(
+                timestamp,
+                String::from(
+                    "90, 92, X1, X2, X9: Due to traffic congestion at 8th & H St NE, buses may experience delays."
+                )
+            )
+            .with_id("32297013-57B6-467F-BC6B-93DFA4115652")
+            .with_kind("Delay")
+            .with_routes(["90", "92", "X1", "X2", "X9"])]
+        );
+    }
+}
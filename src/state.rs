@@ -0,0 +1,178 @@
+//! Places that the time of the last successful run can be kept between runs
+
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use sea_orm::ConnectionTrait;
+
+use crate::Result;
+use crate::database::{prelude::*, run_state};
+
+/// Anything that can remember when the service last finished notifying everyone
+pub trait StateStore {
+    /// Reads the time of the last successful run, or [`None`] if there has not been one yet
+    fn fetch<C: ConnectionTrait>(
+        &self,
+        db: &C,
+    ) -> impl Future<Output = Result<Option<DateTime<Utc>>>> + Send;
+
+    /// Records the time of a successful run
+    fn update<C: ConnectionTrait>(
+        &self,
+        db: &C,
+        timestamp: DateTime<Utc>,
+    ) -> impl Future<Output = Result<()>> + Send;
+}
+
+/// Keeps the timestamp in a plain text file, which is fine as long as only one instance of the service is running
+#[derive(Clone, Debug)]
+pub struct FileStore {
+    path: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl StateStore for FileStore {
+    async fn fetch<C: ConnectionTrait>(&self, _db: &C) -> Result<Option<DateTime<Utc>>> {
+        match tokio::fs::read_to_string(&self.path).await {
+            Ok(buffer) => Ok(Some(DateTime::parse_from_rfc3339(buffer.trim())?.to_utc())),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Writes to a temporary file first and renames it over the original so a crash never leaves it half written
+    async fn update<C: ConnectionTrait>(&self, _db: &C, timestamp: DateTime<Utc>) -> Result<()> {
+        use tokio::io::AsyncWriteExt;
+
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+
+        let mut file = tokio::fs::File::create(&temporary).await?;
+        file.write_all(timestamp.to_rfc3339().as_bytes()).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(temporary, &self.path).await?;
+        Ok(())
+    }
+}
+
+/// Keeps the timestamp in the same database as the users, so several instances of the service can share it
+#[derive(Clone, Debug)]
+pub struct DatabaseStore {
+    key: String, // Lets services watching different sources share one database
+}
+
+impl DatabaseStore {
+    pub fn new(key: impl Into<String>) -> Self {
+        Self { key: key.into() }
+    }
+}
+
+impl StateStore for DatabaseStore {
+    async fn fetch<C: ConnectionTrait>(&self, db: &C) -> Result<Option<DateTime<Utc>>> {
+        use sea_orm::EntityTrait;
+
+        Ok(RunState::find_by_id(self.key.clone())
+            .one(db)
+            .await?
+            .map(|state| state.timestamp))
+    }
+
+    async fn update<C: ConnectionTrait>(&self, db: &C, timestamp: DateTime<Utc>) -> Result<()> {
+        use sea_orm::{ActiveValue, EntityTrait, sea_query::OnConflict};
+
+        RunState::insert(run_state::ActiveModel {
+            key: ActiveValue::Set(self.key.clone()),
+            timestamp: ActiveValue::Set(timestamp),
+        })
+        .on_conflict(
+            OnConflict::column(run_state::Column::Key)
+                .update_column(run_state::Column::Timestamp)
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Either of the built-in stores, chosen at runtime from the command line
+#[derive(Clone, Debug)]
+pub enum Store {
+    File(FileStore),
+    Database(DatabaseStore),
+}
+
+impl StateStore for Store {
+    async fn fetch<C: ConnectionTrait>(&self, db: &C) -> Result<Option<DateTime<Utc>>> {
+        match self {
+            Store::File(store) => store.fetch(db).await,
+            Store::Database(store) => store.fetch(db).await,
+        }
+    }
+
+    async fn update<C: ConnectionTrait>(&self, db: &C, timestamp: DateTime<Utc>) -> Result<()> {
+        match self {
+            Store::File(store) => store.update(db, timestamp).await,
+            Store::Database(store) => store.update(db, timestamp).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use super::{DatabaseStore, FileStore, StateStore};
+
+    #[tokio::test]
+    async fn test_file_store() {
+        use tokio::fs;
+
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        let path = std::env::temp_dir().join("fire-alarm-service-test-file-store.txt");
+        let _ = fs::remove_file(&path).await;
+        let store = FileStore::new(&path);
+        let missing = store.fetch(&db).await.unwrap();
+
+        let timestamp = Utc::now();
+        // Longer than the next value so a write that is not truncated would leave garbage behind
+        fs::write(&path, format!("{}\n", timestamp.to_rfc3339()))
+            .await
+            .unwrap();
+        let fetched = store.fetch(&db).await.unwrap();
+
+        let updated = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        store.update(&db, updated).await.unwrap();
+        let contents = fs::read_to_string(&path).await.unwrap();
+        let refetched = store.fetch(&db).await;
+        match fs::remove_file(&path).await {
+            Ok(_) => {}
+            Err(error) => eprintln!("Failed to remove {}: {error}", path.display()),
+        }
+        assert_eq!(missing, None);
+        assert_eq!(fetched, Some(timestamp));
+        assert_eq!(contents, updated.to_rfc3339());
+        assert_eq!(refetched.unwrap(), Some(updated));
+    }
+
+    #[tokio::test]
+    async fn test_database_store() {
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        crate::setup_db(&db, false).await.unwrap();
+        let store = DatabaseStore::new("test");
+        let other = DatabaseStore::new("other");
+        assert_eq!(store.fetch(&db).await.unwrap(), None);
+
+        let first = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let second = Utc.with_ymd_and_hms(2001, 1, 1, 0, 0, 0).unwrap();
+        store.update(&db, first).await.unwrap();
+        store.update(&db, second).await.unwrap();
+        assert_eq!(store.fetch(&db).await.unwrap(), Some(second));
+        assert_eq!(other.fetch(&db).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,138 @@
+//! Finding station names in incident messages regardless of case, punctuation, or spacing
+
+use caseless::Caseless;
+use unicode_normalization::UnicodeNormalization;
+
+/// Splits the text into case folded words, treating anything that is not a letter or number as a separator
+pub(crate) fn words(text: &str) -> Vec<String> {
+    // Normalizing before and after folding is what Unicode calls a compatibility caseless match
+    let folded: String = text.nfkd().default_case_fold().nfkc().collect();
+    folded
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Joins the words of the text with single spaces and puts a space at either end,
+/// so whole words can be found with a plain substring search, or [`None`] if there are no words
+pub(crate) fn normalize(text: &str) -> Option<String> {
+    let words = words(text);
+    (!words.is_empty()).then(|| format!(" {} ", words.join(" ")))
+}
+
+/// Every spelling of a place split into [`words`], leaving out the ones without any
+pub(crate) type Spellings = Vec<Vec<String>>;
+
+/// Splits each name the place goes by into words, so it can be searched for by [`segments`] without doing it again for every text
+pub(crate) fn spellings<'a>(names: impl IntoIterator<Item = &'a str>) -> Spellings {
+    names
+        .into_iter()
+        .map(words)
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Finds every "between X and Y" or "from X to Y" phrase in the [`words`] of a text where both ends are among the places,
+/// returning the indices of the two places
+pub(crate) fn segments(text: &[String], places: &[Spellings]) -> Vec<(usize, usize)> {
+    // Every place whose name starts at the position, along with where the name ends
+    let places_at = |position: usize| -> Vec<(usize, usize)> {
+        places
+            .iter()
+            .enumerate()
+            .flat_map(|(index, names)| {
+                names
+                    .iter()
+                    .filter(|name| text[position..].starts_with(name))
+                    .map(move |name| (index, position + name.len()))
+            })
+            .collect()
+    };
+
+    let mut segments = Vec::new();
+    for (position, word) in text.iter().enumerate() {
+        let conjunction = match word.as_str() {
+            "between" => "and",
+            "from" => "to",
+            _ => continue,
+        };
+        for (start, after) in places_at(position + 1) {
+            if text.get(after).is_some_and(|word| word == conjunction) {
+                for (end, _) in places_at(after + 1) {
+                    segments.push((start, end));
+                }
+            }
+        }
+    }
+    segments
+}
+
+#[cfg(test)]
+mod test {
+    /// Checks if the phrase appears in the text as whole words, so "power" is found in "Power outage" but not in "powerful"
+    fn contains_words(text: &str, phrase: &str) -> bool {
+        use super::normalize;
+
+        normalize(phrase)
+            .is_some_and(|phrase| normalize(text).unwrap_or_default().contains(&phrase))
+    }
+
+    #[test]
+    fn test_word_boundaries() {
+        assert!(contains_words("Power outage at the yard", "power"));
+        assert!(!contains_words("A powerful storm is passing", "power"));
+        assert!(!contains_words("Trains are rerouted", "Union Station"));
+        assert!(!contains_words("Anything at all", ""));
+    }
+
+    #[test]
+    fn test_case_folding() {
+        assert!(contains_words("hello there", "Hello"));
+        assert!(contains_words("DELAYS AT DUPONT CIRCLE", "Dupont Circle"));
+        assert!(contains_words("Closed on Hauptstraße", "HAUPTSTRASSE"));
+    }
+
+    #[test]
+    fn test_punctuation_and_whitespace() {
+        assert!(contains_words(
+            "Delays at L’Enfant Plaza.",
+            "L'Enfant Plaza"
+        ));
+        assert!(contains_words(
+            "Single tracking at Foggy Bottom GWU",
+            "Foggy Bottom-GWU"
+        ));
+        assert!(contains_words(
+            "Doors stuck at\nfoggy   bottom-gwu!",
+            "Foggy Bottom-GWU"
+        ));
+        assert!(contains_words("Trains skip (high ground)", "high ground"));
+    }
+
+    #[test]
+    fn test_segments() {
+        use super::{spellings, words};
+
+        let places = [
+            vec!["Farragut North"],
+            vec!["Dupont Circle"],
+            vec!["Gallery Pl-Chinatown", "Chinatown"],
+        ]
+        .map(spellings);
+        let segments = |text: &str| super::segments(&words(text), &places);
+        assert_eq!(
+            segments(
+                "Single tracking between Farragut North and Dupont Circle due to a track problem"
+            ),
+            [(0, 1)]
+        );
+        assert_eq!(
+            segments("Shuttle buses from chinatown to Farragut North."),
+            [(2, 0)]
+        );
+        // Both ends have to be known places
+        assert!(segments("Delays between Farragut North and Bethesda").is_empty());
+        assert!(segments("Delays at Farragut North and Dupont Circle").is_empty());
+    }
+}
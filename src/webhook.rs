@@ -0,0 +1,163 @@
+//! HTTP server that partners can push incidents to rather than waiting for them to be polled
+
+use std::sync::Arc;
+
+use axum::{
+    Router,
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode, header},
+    routing::post,
+};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+
+use crate::{Incident, Result};
+
+/// Header carrying the hex encoded HMAC-SHA256 of the request body, optionally prefixed with `sha256=`
+pub const SIGNATURE_HEADER: &str = "X-Signature";
+
+/// Creates the routes for the server, calling the handler with the incidents from every authenticated `POST /incidents`.
+/// The body is the same JSON array of incidents that the binary reads from stdin.
+pub fn router<F, Fut>(secret: impl Into<String>, handler: F) -> Router
+where
+    F: Fn(Vec<Incident>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    Router::new()
+        .route("/incidents", post(receive::<F, Fut>))
+        .with_state(Arc::new(Webhook {
+            secret: secret.into(),
+            handler,
+        }))
+}
+
+struct Webhook<F> {
+    secret: String,
+    handler: F,
+}
+
+impl<F> Webhook<F> {
+    /// Accepts either the shared secret as a bearer token or an HMAC signature of the body made with it
+    fn authenticate(&self, headers: &HeaderMap, body: &[u8]) -> bool {
+        if self.secret.is_empty() {
+            return false; // Otherwise an empty bearer token would be let through
+        }
+        if let Some(signature) = headers.get(SIGNATURE_HEADER) {
+            let Some(signature) = signature
+                .to_str()
+                .ok()
+                .map(|signature| signature.strip_prefix("sha256=").unwrap_or(signature))
+                .and_then(|signature| hex::decode(signature).ok())
+            else {
+                return false;
+            };
+            let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.as_bytes())
+                .expect("HMAC accepts keys of any length");
+            mac.update(body);
+            mac.verify_slice(&signature).is_ok()
+        } else if let Some(token) = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+        {
+            token.as_bytes().ct_eq(self.secret.as_bytes()).into()
+        } else {
+            false
+        }
+    }
+}
+
+async fn receive<F, Fut>(
+    State(webhook): State<Arc<Webhook<F>>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode
+where
+    F: Fn(Vec<Incident>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<()>> + Send + 'static,
+{
+    if !webhook.authenticate(&headers, &body) {
+        return StatusCode::UNAUTHORIZED;
+    }
+    let incidents: Vec<Incident> = match serde_json::from_slice(&body) {
+        Ok(incidents) => incidents,
+        Err(_) => return StatusCode::BAD_REQUEST,
+    };
+    match (webhook.handler)(incidents).await {
+        Ok(()) => StatusCode::OK,
+        Err(error) => {
+            #[cfg(feature = "log")]
+            log::error!("{error}");
+            #[cfg(not(feature = "log"))]
+            eprintln!("{error}");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use hmac::{Hmac, Mac};
+    use reqwest::StatusCode;
+    use sha2::Sha256;
+    use tokio::sync::mpsc;
+
+    use super::SIGNATURE_HEADER;
+    use crate::Incident;
+
+    const BODY: &str = r#"[{"timestamp": "2000-01-01T00:00:00Z", "description": "Hello there"}]"#;
+
+    #[tokio::test]
+    async fn test_authenticate() {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let router = super::router("secret", move |incidents: Vec<Incident>| {
+            let sender = sender.clone();
+            async move {
+                sender.send(incidents).unwrap();
+                Ok(())
+            }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/incidents", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
+        mac.update(BODY.as_bytes());
+        let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+
+        let client = reqwest::Client::new();
+        let post = |body: &'static str| client.post(&url).body(body);
+        let responses = [
+            post(BODY).bearer_auth("secret"),
+            post(BODY).header(SIGNATURE_HEADER, signature.clone()),
+            post(BODY).bearer_auth("wrong"),
+            post(BODY).header(SIGNATURE_HEADER, "sha256=00"),
+            post(BODY),
+            post("not json").bearer_auth("secret"),
+        ];
+        let mut statuses = Vec::new();
+        for request in responses {
+            statuses.push(request.send().await.unwrap().status());
+        }
+
+        assert_eq!(
+            statuses,
+            [
+                StatusCode::OK,
+                StatusCode::OK,
+                StatusCode::UNAUTHORIZED,
+                StatusCode::UNAUTHORIZED,
+                StatusCode::UNAUTHORIZED,
+                StatusCode::BAD_REQUEST,
+            ]
+        );
+        for _ in 0..2 {
+            let incidents = receiver.recv().await.unwrap();
+            assert_eq!(incidents.len(), 1);
+            assert_eq!(incidents[0].description, "Hello there");
+        }
+        assert!(receiver.try_recv().is_err());
+    }
+}